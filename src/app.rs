@@ -14,58 +14,135 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
 use std::ops::Range;
 use std::path::Path;
 use std::str;
-use chrono::{Local, NaiveDateTime};
+use chrono::{Duration, Local, NaiveDateTime};
 use eframe::{App, CreationContext, Frame};
-use egui::{CentralPanel, Context, FontData, FontDefinitions, FontFamily, FontTweak, TextBuffer, TopBottomPanel};
+use egui::{Button, CentralPanel, ComboBox, Context, DragValue, Event, FontData, FontDefinitions, FontFamily, FontTweak, Id, Modifiers, TextBuffer, TopBottomPanel, Ui};
 use egui::text::{CCursor, CCursorRange};
 use egui::widgets::TextEdit;
+use egui::widgets::text_edit::TextEditState;
 use grus_lib::Store;
 use grus_lib::types::Session;
 #[cfg(target_os = "android")]
 use crate::android::JniWrapper;
-use crate::node::Tree;
-use crate::ftree::FlatTree;
+use crate::completion::Completions;
+use crate::history::{Change, History};
+use crate::next::{NextActions, NextActionsView, Urgency};
+use crate::node::{Displayable, Tree};
+use crate::ordering::{ChildOrders, SortMode};
+use crate::outline;
+use crate::rename::{PatternField, RenamePattern};
+use crate::ftree::{FlatTree, Layout, ViewState, INDENT_SPACING};
+use crate::selection_set::SelectionSets;
+use crate::series::{Series, SeriesList};
+use crate::snapshot::{resolve, Snapshot};
+use crate::template::{Template, Templates};
+use crate::trash::Trash;
 use grus_gui_lib::datepicker::DatePicker;
 use crate::vboard::{Key, VBoard};
 
+const ARCHIVE_ROOT: u64 = 1;
+
 pub struct Grus {
 	store: Store,
 	tree: Tree,
+	series: SeriesList,
+	trash: Trash,
+	selection_sets: SelectionSets,
+	templates: Templates,
+	template_draft: Option<TemplateDraft>,
+	history: History,
+	roots: &'static [&'static str],
 	root_pid: u64,
 	root_id: u64,
 	stack: Vec<(u64, u64)>,
+	view: ViewState,
+	anchor: Option<(u64, u64)>,
 	todo: Action,
 	vboard_text: String,
 	vboard_caps: bool,
+	rename_pattern: RenamePattern,
+	outline: Vec<Snapshot>,
+	cut: Option<(u64, u64)>,
+	duplicate_options: DuplicateOptions,
+	merge: Option<MergeDraft>,
+	urgency: Urgency,
+	next_actions: NextActions,
+	next_view: bool,
+	split_children: bool,
+	confirm_delete: bool,
+	status: String,
 	start_date: NaiveDateTime,
 	end_date: NaiveDateTime,
+	until_date: NaiveDateTime,
 	#[cfg(target_os = "android")] jniwr: JniWrapper,
 }
 
 impl Grus {
 	pub fn new<P: AsRef<Path>>(
 		path: P,
-		n_roots: usize,
+		roots: &'static [&'static str],
 		#[cfg(target_os = "android")] jniwr: JniWrapper
 	) -> Result<Self, Error> {
-		let store = Store::open(path, n_roots)?;
-		let tree = Tree::from_store(&store)?;
-		Ok(Grus {
+		let mut series = SeriesList::open(path.as_ref())?;
+		let completions = Completions::open(path.as_ref())?;
+		let orders = ChildOrders::open(path.as_ref())?;
+		let mut trash = Trash::open(path.as_ref())?;
+		trash.purge(Local::now().naive_local());
+		trash.save()?;
+		let mut trashed = HashSet::new();
+		for entry in trash.iter() {
+			entry.snapshot.node_ids(&mut trashed);
+		}
+		let selection_sets = SelectionSets::open(path.as_ref())?;
+		let templates = Templates::open(path.as_ref())?;
+		let urgency = Urgency::open(path.as_ref())?;
+		let store = Store::open(path, roots.len())?;
+		let mut tree = Tree::from_store(&store, completions, orders)?;
+		tree.retain_sidecars(|id| trashed.contains(&id))?;
+		series.retain_nodes(|id| tree.contains(id) || trashed.contains(&id));
+		series.save()?;
+		let mut grus = Grus {
 			store,
 			tree,
+			series,
+			trash,
+			selection_sets,
+			templates,
+			template_draft: None,
+			history: History::default(),
+			roots,
 			root_pid: 0,
 			root_id: 0,
 			stack: Vec::new(),
+			view: ViewState::default(),
+			anchor: None,
 			todo: Action::None,
 			vboard_text: "".into(),
 			vboard_caps: false,
+			rename_pattern: RenamePattern::default(),
+			outline: Vec::new(),
+			cut: None,
+			duplicate_options: DuplicateOptions { sessions: true, shift_days: 0 },
+			merge: None,
+			urgency,
+			next_actions: NextActions::default(),
+			next_view: false,
+			split_children: false,
+			confirm_delete: true,
+			status: String::new(),
 			start_date: NaiveDateTime::default(),
 			end_date: NaiveDateTime::default(),
+			until_date: NaiveDateTime::default(),
 			#[cfg(target_os = "android")] jniwr,
-		})
+		};
+		grus.expand_series()?;
+		Ok(grus)
 	}
 
 	pub fn with_scale(self, cc: &CreationContext, ppp: f32) -> Self {
@@ -91,50 +168,590 @@ impl Grus {
 		self
 	}
 
+	fn current_root(&self) -> u64 {
+		self.stack.first().map_or(self.root_id, |&(_, id)| id)
+	}
+
+	fn outline_targets(&self) -> Vec<(u64, u64)> {
+		let mut targets: Vec<(u64, u64)> = self.tree.selections().map(|(&pid, &id)| (pid, id)).collect();
+		if targets.is_empty() {
+			targets.extend(self.view.hovered);
+		}
+		targets.retain(|&(pid, id)| pid != id);
+		targets.sort_by_key(|row| self.view.rows.iter().position(|r| r == row));
+		targets
+	}
+
+	// Rows whose ancestor is selected too travel along with it, so only the topmost ones are acted on.
+	fn top_selections(&self) -> Vec<(u64, u64)> {
+		let selections: Vec<(u64, u64)> = self.tree.selections()
+			.map(|(&pid, &id)| (pid, id))
+			.filter(|&(pid, id)| pid != id && id != self.root_id)
+			.collect();
+		selections.iter()
+			.copied()
+			.filter(|&(_, id)| !selections.iter().any(|&(_, other)| other != id && self.tree.descends_from(id, other)))
+			.collect()
+	}
+
+	fn leave_removed_roots(&mut self) {
+		while !self.tree.contains(self.root_id) {
+			let Some((root_pid, root_id)) = self.stack.pop() else { break };
+			self.root_pid = root_pid;
+			self.root_id = root_id;
+		}
+	}
+
+	fn grandparent(&self, pid: u64, id: u64) -> Option<u64> {
+		let i = self.view.rows.iter().position(|&row| row == (pid, id))?;
+		match self.view.rows[..i].iter().rev().find(|&&(_, row_id)| row_id == pid) {
+			Some(&(gpid, _)) => Some(gpid),
+			None if pid == self.root_id && self.root_pid != self.root_id => Some(self.root_pid),
+			None => None,
+		}
+	}
+
+	fn move_targets(&mut self, moves: Vec<(u64, u64, u64)>) -> Result<(), Error> {
+		for &(src_pid, id, dest_pid) in &moves {
+			if self.tree.is_selected(src_pid, id) {
+				self.tree.toggle(src_pid, id);
+				self.tree.select(dest_pid, id, true);
+			}
+		}
+		let changes = moves.into_iter()
+			.map(|(src_pid, id, dest_pid)| Change::Move { src_pid, id, dest_pid })
+			.collect();
+		self.apply(changes)
+	}
+
+	fn ordered_selection_ids(&self) -> Vec<u64> {
+		let mut ids: Vec<u64> = self.tree.selection_ids().copied().collect();
+		ids.sort_by_key(|id| (self.view.rows.iter().position(|&(_, row_id)| row_id == *id).unwrap_or(usize::MAX), *id));
+		ids
+	}
+
+	fn duplicate(&self, id: u64) -> Snapshot {
+		let mut snapshot = Snapshot::copy(&self.tree, id);
+		if !self.duplicate_options.sessions {
+			snapshot.clear_sessions();
+		}
+		snapshot.shift_dates(Duration::days(self.duplicate_options.shift_days));
+		snapshot
+	}
+
+	fn apply(&mut self, changes: Vec<Change>) -> Result<(), Error> {
+		let inverse = self.apply_changes(changes)?;
+		self.history.record(inverse);
+		self.tree.rebuild(&self.store)?;
+		Ok(())
+	}
+
+	fn apply_changes(&mut self, changes: Vec<Change>) -> Result<Vec<Change>, Error> {
+		let mut writer = self.store.writer()?;
+		let mut inverse = Vec::new();
+		let mut restored = HashSet::new();
+		for change in changes {
+			if let Change::Insert { snapshot, .. } = &change {
+				snapshot.node_ids(&mut restored);
+			}
+			match change.apply(&mut writer, &mut self.tree, &mut self.history.remap) {
+				Ok(change) => inverse.push(change),
+				Err(err) => {
+					// Nothing is committed, but the tree already mirrors the changes before the failing one.
+					drop(writer);
+					self.tree.rebuild(&self.store)?;
+					return Err(err);
+				}
+			}
+		}
+		writer.commit()?;
+		inverse.reverse();
+		let ids: HashMap<u64, u64> = restored.into_iter()
+			.map(|id| (id, resolve(&self.history.remap, id)))
+			.filter(|&(old_id, id)| old_id != id)
+			.collect();
+		if !ids.is_empty() {
+			self.rekey(&ids)?;
+		}
+		Ok(inverse)
+	}
+
+	// Restored nodes come back under new ids, so whatever is kept by id follows them.
+	fn rekey(&mut self, ids: &HashMap<u64, u64>) -> io::Result<()> {
+		self.tree.rekey(ids)?;
+		self.series.rekey(ids);
+		self.series.save()?;
+		self.selection_sets.rekey(ids);
+		self.selection_sets.save()
+	}
+
+	fn discard_history(&mut self, err: Error) {
+		eprintln!("Failed to replay history, discarding it: {}", err);
+		self.history.clear();
+	}
+
+	fn expand_series(&mut self) -> Result<(), Error> {
+		let changes = self.series_changes(Local::now().naive_local())?;
+		self.apply_series(changes)
+	}
+
+	// Undo can't roll the series sidecar back along with the sessions, so series writes stay out of history.
+	fn apply_series(&mut self, changes: Vec<Change>) -> Result<(), Error> {
+		if changes.is_empty() { return Ok(()) }
+		self.apply_changes(changes)?;
+		self.tree.rebuild(&self.store)?;
+		Ok(())
+	}
+
+	fn series_changes(&mut self, now: NaiveDateTime) -> Result<Vec<Change>, Error> {
+		let changes = self.series.expand(now).into_iter()
+			.filter(|&(id, _)| self.tree.contains(id))
+			.map(|(id, session)| Change::AddSession { id, session })
+			.collect();
+		self.series.save()?;
+		Ok(changes)
+	}
+
+	fn upcoming_sessions(&self, series: &Series, now: NaiveDateTime) -> Vec<Change> {
+		series.upcoming(now)
+			.filter(|&(id, session)| self.tree.contains(id) && self.tree.node_at(id).sessions.iter().any(|s| s.start == session.start && s.end == session.end))
+			.map(|(id, session)| Change::RemoveSession { id, session })
+			.collect()
+	}
+
 	pub fn perform_action(&mut self, action: Action) -> Result<(), Error> {
 		match action {
 			Action::Add(_, id) => {
-				let mut writer = self.store.writer()?;
-				writer.add_child(id, &self.vboard_text)?;
-				writer.commit()?;
-				self.tree.rebuild(&self.store)?;
+				let name = self.vboard_text.clone();
+				self.apply(vec![Change::Add { pid: id, name }])?;
+				self.vboard_text.clear();
+			}
+			Action::Split(pid, id) => {
+				let parts: Vec<String> = split_list(&self.vboard_text).map(String::from).collect();
 				self.vboard_text.clear();
+				let changes = if self.split_children {
+					parts.into_iter().map(|name| Change::Add { pid: id, name }).collect()
+				} else {
+					let mut parts = parts.into_iter();
+					let Some(first) = parts.next() else { return Ok(()) };
+					let mut changes = vec![Change::Rename { id, name: first }];
+					changes.extend(parts.map(|name| Change::Add { pid, name }));
+					changes
+				};
+				self.apply(changes)?;
 			}
 			Action::Delete(pid, id) => {
-				let mut writer = self.store.writer()?;
-				writer.delete(pid, id)?;
-				writer.commit()?;
-				self.tree.rebuild(&self.store)?;
+				let removed = self.tree.removed_by_delete(pid, id);
+				let snapshot = Snapshot::capture(&self.tree, id, &removed);
+				self.apply(vec![Change::Remove { pid, id }])?;
+				let now = Local::now().naive_local();
+				self.trash.push(pid, snapshot, now);
+				self.trash.purge(now);
+				self.trash.save()?;
+				self.leave_removed_roots();
+			}
+			Action::Restore(i) => {
+				let Some(entry) = self.trash.take(i) else { return Ok(()) };
+				let pid = if self.tree.contains(entry.pid) { entry.pid } else { 0 };
+				self.apply(vec![Change::Insert { pid, snapshot: entry.snapshot }])?;
+				self.trash.save()?;
+			}
+			Action::Purge(i) => {
+				self.trash.take(i);
+				self.trash.save()?;
+			}
+			Action::SetRetention(days) => {
+				self.trash.retention_days = days;
+				self.trash.purge(Local::now().naive_local());
+				self.trash.save()?;
 			}
 			Action::Rename => {
-				let mut writer = self.store.writer()?;
-				for &id in self.tree.selection_ids() {
-					writer.rename(id, &self.vboard_text)?;
+				let changes = self.tree.selection_ids()
+					.map(|&id| Change::Rename { id, name: self.vboard_text.clone() })
+					.collect();
+				self.apply(changes)?;
+				self.vboard_text.clear();
+			}
+			Action::Copy(id) => {
+				self.status = format!("Copied \"{}\"", self.tree.node_at(id).name);
+			}
+			Action::Cut(pid, id) => {
+				self.cut = Some((pid, id));
+				self.status = format!("Cut \"{}\"", self.tree.node_at(id).name);
+			}
+			Action::PasteCut(dest_pid) => if let Some((src_pid, id)) = self.cut.take() {
+				let movable = self.tree.contains(id)
+					&& self.tree.contains(src_pid)
+					&& self.tree.is_child(src_pid, id)
+					&& src_pid != dest_pid
+					&& !self.tree.descends_from(dest_pid, id);
+				if movable {
+					self.apply(vec![Change::Move { src_pid, id, dest_pid }])?;
+					self.status = format!("Moved \"{}\"", self.tree.node_at(id).name);
+				} else {
+					self.status = "The cut task has moved since, cut it again".into();
 				}
-				writer.commit()?;
-				self.tree.rebuild(&self.store)?;
+			}
+			Action::PasteOutline(_, id) => {
+				let changes = self.outline.drain(..).map(|snapshot| Change::Copy { pid: id, snapshot }).collect();
+				self.apply(changes)?;
 				self.vboard_text.clear();
 			}
+			Action::BulkRename => {
+				let ids = self.ordered_selection_ids();
+				let names = self.rename_pattern.rename_all(ids.iter().map(|&id| self.tree.node_at(id).name.as_str()));
+				let Ok(names) = names else { return Ok(()) };
+				let changes = ids.into_iter()
+					.zip(names)
+					.filter(|(id, name)| self.tree.node_at(*id).name != *name)
+					.map(|(id, name)| Change::Rename { id, name })
+					.collect();
+				self.apply(changes)?;
+				self.rename_pattern.clear();
+			}
 			Action::SetDueDate => {
-				let mut writer = self.store.writer()?;
-				for &id in self.tree.selection_ids() {
-					writer.set_due_date(id, self.end_date)?;
+				let changes = self.tree.selection_ids()
+					.map(|&id| Change::SetDueDate { id, due_date: Some(self.end_date) })
+					.collect();
+				self.apply(changes)?;
+			}
+			Action::AddSession => {
+				let session = Session { start: self.start_date, end: self.end_date };
+				let changes = self.tree.selection_ids()
+					.map(|&id| Change::AddSession { id, session })
+					.collect();
+				self.apply(changes)?;
+			}
+			Action::Bulk(Bulk::Delete) => {
+				let selections = self.top_selections();
+				if selections.is_empty() { return Ok(()) }
+				let changes = selections.iter().map(|&(pid, id)| Change::Remove { pid, id }).collect();
+				let inverse = self.apply_changes(changes)?;
+				let now = Local::now().naive_local();
+				// A node selected under several parents is only worth restoring from its final removal.
+				for (pid, snapshot) in removed_subtrees(&inverse) {
+					self.trash.push(pid, snapshot, now);
 				}
-				writer.commit()?;
+				self.history.record(inverse);
 				self.tree.rebuild(&self.store)?;
+				self.trash.purge(now);
+				self.trash.save()?;
+				self.tree.clear_selections();
+				self.leave_removed_roots();
+				self.status = format!("Deleted {} tasks", selections.len());
 			}
-			Action::AddSession => {
-				let mut writer = self.store.writer()?;
-				for &id in self.tree.selection_ids() {
-					writer.add_session(id, &Session { start: self.start_date, end: self.end_date })?;
+			Action::Bulk(Bulk::Complete) => {
+				let ids: Vec<u64> = self.tree.selection_ids().copied().collect();
+				self.tree.set_done(ids.iter().copied(), Some(Local::now().naive_local()))?;
+				self.status = format!("Completed {} tasks", ids.len());
+			}
+			Action::Bulk(Bulk::ClearDueDate) => {
+				let changes: Vec<Change> = self.tree.selection_ids()
+					.filter(|&&id| self.tree.node_at(id).due_date.is_some())
+					.map(|&id| Change::SetDueDate { id, due_date: None })
+					.collect();
+				self.status = format!("Cleared {} due dates", changes.len());
+				self.apply(changes)?;
+			}
+			Action::BulkTo(Bulk::Move, dest_pid) => {
+				let changes: Vec<Change> = self.top_selections().into_iter()
+					.filter(|&(src_pid, id)| src_pid != dest_pid && !self.tree.descends_from(dest_pid, id))
+					.map(|(src_pid, id)| Change::Move { src_pid, id, dest_pid })
+					.collect();
+				self.status = format!("Moved {} tasks", changes.len());
+				self.apply(changes)?;
+				self.tree.clear_selections();
+			}
+			Action::BulkTo(Bulk::Link, pid) => {
+				let changes: Vec<Change> = self.tree.selection_ids()
+					.filter(|&&id| !self.tree.is_child(pid, id) && !self.tree.descends_from(pid, id))
+					.map(|&id| Change::Link { id, pid })
+					.collect();
+				self.status = format!("Linked {} tasks", changes.len());
+				self.apply(changes)?;
+			}
+			Action::Bulk(Bulk::Duplicate) => {
+				let changes: Vec<Change> = self.tree.selections()
+					.filter(|&(&pid, &id)| pid != id)
+					.map(|(&pid, &id)| Change::Copy { pid, snapshot: self.duplicate(id) })
+					.collect();
+				self.status = format!("Duplicated {} tasks", changes.len());
+				self.apply(changes)?;
+			}
+			Action::BulkTo(Bulk::Duplicate, pid) => {
+				let changes: Vec<Change> = self.tree.selection_ids()
+					.map(|&id| Change::Copy { pid, snapshot: self.duplicate(id) })
+					.collect();
+				self.status = format!("Duplicated {} tasks", changes.len());
+				self.apply(changes)?;
+			}
+			Action::SaveTemplate => {
+				let Some(&id) = self.tree.selection_ids().next() else { return Ok(()) };
+				let snapshot = Snapshot::copy(&self.tree, id);
+				let name = mem::take(&mut self.vboard_text);
+				self.templates.insert(Template::new(name, snapshot, Local::now().naive_local()));
+				self.templates.save()?;
+			}
+			Action::UseTemplate(i) => if let Some(template) = self.templates.get(i) {
+				self.template_draft = Some(TemplateDraft {
+					index: i,
+					values: template.placeholders().into_iter().map(|p| (p, String::new())).collect(),
+					anchor: template.anchor,
+					focused: 0,
+				});
+				self.todo = Action::InstantiateTemplate;
+			}
+			Action::DeleteTemplate(i) => {
+				self.templates.remove(i);
+				self.templates.save()?;
+			}
+			Action::BulkTo(Bulk::Instantiate, pid) => {
+				let Some(draft) = self.template_draft.take() else { return Ok(()) };
+				let Some(template) = self.templates.get(draft.index) else { return Ok(()) };
+				let values = draft.values.into_iter().collect::<HashMap<_, _>>();
+				let snapshot = template.instantiate(&values, draft.anchor);
+				self.status = format!("Added \"{}\"", template.name);
+				self.apply(vec![Change::Copy { pid, snapshot }])?;
+			}
+			Action::Bulk(_) | Action::BulkTo(..) | Action::PickParent(_) | Action::DuplicateOptions => {}
+			Action::EditName(pid, id) => {
+				self.view.editing = Some((pid, id));
+				self.view.edit_text = self.tree.node_at(id).name.clone();
+				self.view.focus_edit = true;
+				self.todo = Action::EditName(pid, id);
+			}
+			Action::CommitRename => if let Some((_, id)) = self.view.editing.take() {
+				self.todo = Action::None;
+				let name = mem::take(&mut self.view.edit_text);
+				self.apply(vec![Change::Rename { id, name }])?;
+			}
+			Action::CancelRename => {
+				self.view.editing = None;
+				self.view.edit_text.clear();
+				self.todo = Action::None;
+			}
+			Action::MergeOptions => if let [into, from] = self.ordered_selection_ids()[..] {
+				if self.tree.descends_from(into, from) || self.tree.descends_from(from, into) {
+					self.status = "Can't merge a task with one nested under it".into();
+					return Ok(());
+				}
+				self.merge = Some(MergeDraft { into, from, due: MergeDue::Earlier });
+				self.todo = Action::MergeOptions;
+			}
+			Action::Merge => if let Some(MergeDraft { into, from, due }) = self.merge.take() {
+				let mut changes = Vec::new();
+				for child in self.tree.children(from) {
+					if self.tree.is_child(into, child.id) {
+						changes.push(Change::Remove { pid: from, id: child.id });
+					} else {
+						changes.push(Change::Move { src_pid: from, id: child.id, dest_pid: into });
+					}
+				}
+				for &session in &self.tree.node_at(from).sessions {
+					changes.push(Change::AddSession { id: into, session });
+				}
+				for &pid in self.tree.parent_ids(from) {
+					if !self.tree.is_child(pid, into) {
+						changes.push(Change::Link { id: into, pid });
+					}
+				}
+				let (into_due, from_due) = (self.tree.node_at(into).due_date, self.tree.node_at(from).due_date);
+				let due_date = match due {
+					MergeDue::Earlier => into_due.into_iter().chain(from_due).min(),
+					MergeDue::First => into_due,
+					MergeDue::Second => from_due,
+				};
+				if due_date != into_due {
+					changes.push(Change::SetDueDate { id: into, due_date });
+				}
+				changes.extend(self.tree.parent_ids(from).iter().map(|&pid| Change::Remove { pid, id: from }));
+				self.tree.clear_selections();
+				self.status = format!("Merged into \"{}\"", self.tree.node_at(into).name);
+				self.apply(changes)?;
+				self.leave_removed_roots();
+			}
+			Action::Indent => {
+				let targets = self.outline_targets();
+				let moves = targets.iter()
+					.filter_map(|&(pid, id)| {
+						let skip = |sibling: u64| targets.contains(&(pid, sibling));
+						let dest_pid = self.tree.previous_sibling(pid, id, skip)?;
+						Some((pid, id, dest_pid))
+					})
+					.collect();
+				self.move_targets(moves)?;
+			}
+			Action::Outdent => {
+				let moves = self.outline_targets().into_iter()
+					.filter_map(|(pid, id)| {
+						let dest_pid = self.grandparent(pid, id)?;
+						(!self.tree.is_child(dest_pid, id)).then_some((pid, id, dest_pid))
+					})
+					.collect();
+				self.move_targets(moves)?;
+			}
+			Action::Undo => if let Some(changes) = self.history.pop_undo() {
+				// Undoing a delete brings the subtree back, so its trash entry would restore a second copy.
+				let restored: Vec<(u64, u64)> = changes.iter().filter_map(|change| match change {
+					Change::Insert { pid, snapshot } => Some((*pid, snapshot.id())),
+					_ => None,
+				}).collect();
+				match self.apply_changes(changes) {
+					Ok(inverse) => {
+						self.history.push_redo(inverse);
+						for (pid, id) in restored {
+							self.trash.discard(pid, id);
+						}
+						self.trash.save()?;
+					}
+					Err(err) => self.discard_history(err),
 				}
-				writer.commit()?;
 				self.tree.rebuild(&self.store)?;
+				self.leave_removed_roots();
+			}
+			Action::Redo => if let Some(changes) = self.history.pop_redo() {
+				match self.apply_changes(changes) {
+					Ok(inverse) => {
+						let now = Local::now().naive_local();
+						for (pid, snapshot) in removed_subtrees(&inverse) {
+							self.trash.push(pid, snapshot, now);
+						}
+						self.history.push_undo(inverse);
+						self.trash.save()?;
+					}
+					Err(err) => self.discard_history(err),
+				}
+				self.tree.rebuild(&self.store)?;
+				self.leave_removed_roots();
+			}
+			Action::AddSeries => {
+				let ids: Vec<u64> = self.tree.selection_ids().copied().collect();
+				if ids.is_empty() {
+					self.status = "Select the tasks to repeat first".into();
+					return Ok(());
+				}
+				let first = Session { start: self.start_date, end: self.end_date };
+				self.series.insert(ids, first, self.until_date);
+				self.series.save()?;
+				self.expand_series()?;
+			}
+			Action::EditSeries(sid) => {
+				let now = Local::now().naive_local();
+				let Some(series) = self.series.get(sid) else { return Ok(()) };
+				let mut changes = self.upcoming_sessions(series, now);
+				if let Some(series) = self.series.get_mut(sid) {
+					series.set(Session { start: self.start_date, end: self.end_date }, self.until_date, now);
+				}
+				changes.extend(self.series_changes(now)?);
+				self.apply_series(changes)?;
+			}
+			Action::DeleteSeries(sid) => {
+				let Some(series) = self.series.remove(sid) else { return Ok(()) };
+				let changes = self.upcoming_sessions(&series, Local::now().naive_local());
+				self.series.save()?;
+				self.apply_series(changes)?;
+			}
+			Action::Archive => {
+				let dest_pid = if self.current_root() == ARCHIVE_ROOT { 0 } else { ARCHIVE_ROOT };
+				let changes = self.top_selections().into_iter()
+					.filter(|&(src_pid, id)| src_pid != dest_pid && !self.tree.is_child(dest_pid, id) && !self.tree.descends_from(dest_pid, id))
+					.map(|(src_pid, id)| Change::Move { src_pid, id, dest_pid })
+					.collect();
+				self.apply(changes)?;
+				self.tree.clear_selections();
+			}
+			Action::SwitchRoot(root) => {
+				self.stack.clear();
+				self.root_pid = root;
+				self.root_id = root;
+				self.tree.clear_selections();
+			}
+			Action::Toggle(pid, id) => {
+				self.tree.toggle(pid, id);
+				self.anchor = Some((pid, id));
+			}
+			Action::SelectRange(pid, id) => {
+				let end = self.view.rows.iter().position(|&row| row == (pid, id));
+				let start = self.anchor.and_then(|anchor| self.view.rows.iter().position(|&row| row == anchor)).or(end);
+				if let (Some(start), Some(end)) = (start, end) {
+					for &(pid, id) in &self.view.rows[start.min(end)..=start.max(end)] {
+						self.tree.select(pid, id, true);
+					}
+				}
+				self.anchor = Some((pid, id));
+			}
+			Action::SelectSubtree(pid, id) => self.tree.select_subtree(pid, id),
+			Action::SelectVisible => for &(pid, id) in &self.view.rows {
+				self.tree.select(pid, id, true);
+			}
+			Action::InvertSelection => for &(pid, id) in &self.view.rows {
+				self.tree.toggle(pid, id);
+			}
+			Action::SaveSelection => {
+				let selections = self.tree.selections().map(|(&pid, &id)| (pid, id)).collect();
+				self.selection_sets.insert(self.vboard_text.clone(), selections);
+				self.selection_sets.save()?;
+				self.vboard_text.clear();
+			}
+			Action::SelectionSet(i, op) => {
+				let Some(set) = self.selection_sets.get(i) else { return Ok(()) };
+				let selections: Vec<(u64, u64)> = set.selections.iter()
+					.copied()
+					.filter(|&(pid, id)| self.tree.contains(pid) && self.tree.contains(id))
+					.collect();
+				match op {
+					SetOp::Restore => {
+						self.tree.clear_selections();
+						for (pid, id) in selections {
+							self.tree.select(pid, id, true);
+						}
+					}
+					SetOp::Union => for (pid, id) in selections {
+						self.tree.select(pid, id, true);
+					}
+					SetOp::Intersection => {
+						let current: Vec<(u64, u64)> = self.tree.selections().map(|(&pid, &id)| (pid, id)).collect();
+						for (pid, id) in current {
+							if !selections.contains(&(pid, id)) {
+								self.tree.select(pid, id, false);
+							}
+						}
+					}
+					SetOp::Delete => {
+						self.selection_sets.remove(i);
+						self.selection_sets.save()?;
+					}
+				}
+			}
+			Action::Sort(id, mode) => self.tree.set_sort_mode(id, mode)?,
+			Action::PersistOrder(id, mode) => {
+				self.tree.persist_order(id, mode)?;
+				self.status = format!("Saved {} order", mode.label().to_lowercase());
+			}
+			Action::SaveUrgency => self.urgency.save()?,
+			Action::ClearSelection => {
+				self.tree.clear_selections();
+				self.anchor = None;
+			}
+			Action::ToggleDone(id) => {
+				let at = match self.tree.node_at(id).done {
+					Some(_) => None,
+					None => Some(Local::now().naive_local()),
+				};
+				self.tree.set_done([id], at)?;
 			}
-			Action::Toggle(pid, id) => self.tree.toggle(pid, id),
 			Action::Import => {
 				#[cfg(target_os = "android")]
 				self.jniwr.import()?;
+				self.history.clear();
+				// Everything kept by id belongs to the store that was replaced.
+				self.tree.clear_sidecars()?;
+				self.series.clear();
+				self.series.save()?;
+				self.selection_sets.clear();
+				self.selection_sets.save()?;
+				self.trash.clear();
+				self.trash.save()?;
 				self.tree.rebuild(&self.store)?;
 			}
 			Action::Export => {
@@ -161,14 +778,73 @@ impl Grus {
 impl App for Grus {
 	fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
 		let mut action = Action::None;
+		let current_root = self.current_root();
+		let delete_targets = if self.todo == Action::Bulk(Bulk::Delete) { self.top_selections() } else { Vec::new() };
+		let rename_ids = if self.todo == Action::BulkRename { self.ordered_selection_ids() } else { Vec::new() };
+
+		if self.todo == Action::None {
+			ctx.input_mut(|i| {
+				if i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, egui::Key::Z) {
+					action = Action::Redo;
+				} else if i.consume_key(Modifiers::COMMAND, egui::Key::Z) {
+					action = Action::Undo;
+				} else if i.consume_key(Modifiers::SHIFT, egui::Key::Tab) {
+					action = Action::Outdent;
+				} else if i.consume_key(Modifiers::NONE, egui::Key::Tab) {
+					action = Action::Indent;
+				}
+			});
+			if let Some((pid, id)) = self.view.hovered {
+				let mut pasted = None;
+				ctx.input(|i| for event in &i.events {
+					match event {
+						Event::Copy => action = Action::Copy(id),
+						Event::Cut => action = Action::Cut(pid, id),
+						Event::Paste(text) => pasted = Some(text.clone()),
+						_ => {}
+					}
+				});
+				match (action, pasted) {
+					(Action::Copy(id) | Action::Cut(_, id), _) => {
+						ctx.output_mut(|o| o.copied_text = outline::format(&self.tree, id));
+					}
+					(_, Some(_)) if self.cut.is_some() => action = Action::PasteCut(id),
+					(_, Some(text)) => {
+						self.outline = outline::parse(&text);
+						if !self.outline.is_empty() {
+							self.todo = Action::PasteOutline(pid, id);
+							self.tree.highlighted = Some(id);
+						}
+					}
+					_ => {}
+				}
+			}
+		}
 
 		TopBottomPanel::top("bar").show_separator_line(false).show(ctx, |ui| {
 			ui.add_space(30.0);
 			ui.horizontal(|ui| {
 				if ui.button("󰁍").clicked() { action = Action::MoveOut }
+				#[cfg(target_os = "android")]
+				if ui.add_enabled(self.history.can_undo(), Button::new("󰕌")).clicked() { action = Action::Undo }
+				#[cfg(target_os = "android")]
+				if ui.add_enabled(self.history.can_redo(), Button::new("󰑎")).clicked() { action = Action::Redo }
+				let mut root = current_root;
+				ComboBox::from_id_source("root")
+					.selected_text(self.roots[root as usize])
+					.show_ui(ui, |ui| {
+						for (i, name) in self.roots.iter().enumerate() {
+							ui.selectable_value(&mut root, i as u64, *name);
+						}
+					});
+				if root != current_root {
+					action = Action::SwitchRoot(root);
+				}
 				if ui.button("󰥝").clicked() { action = Action::Import }
 				if ui.button("󰥞").clicked() { action = Action::Export }
-				if ui.button("󱰘").clicked() { self.todo = Action::Rename }
+				if ui.button("󱰘").clicked() {
+					self.todo = if self.tree.selection_count() > 1 { Action::BulkRename } else { Action::Rename }
+				}
 				if ui.button("󰃰").clicked() {
 					self.end_date = Local::now().naive_local();
 					self.todo = Action::SetDueDate
@@ -178,62 +854,158 @@ impl App for Grus {
 					self.end_date = Local::now().naive_local();
 					self.todo = Action::AddSession
 				}
+				if ui.button("󰑖").clicked() { self.todo = Action::ListSeries }
+				if ui.button("󰩹").clicked() { self.todo = Action::ListTrash }
+				if self.roots.len() > ARCHIVE_ROOT as usize {
+					let archive_icon = if current_root == ARCHIVE_ROOT { "󱉛" } else { "󱉙" };
+					if ui.button(archive_icon).clicked() { action = Action::Archive }
+				}
+				if ui.selectable_label(self.next_view, "󰝖").clicked() { self.next_view = !self.next_view }
+				ui.menu_button("󰕏", |ui| {
+					if ui.radio(self.view.depth_limit.is_none(), "All levels").clicked() {
+						self.view.depth_limit = None;
+						ui.close_menu();
+					}
+					for level in 1..=6 {
+						if ui.radio(self.view.depth_limit == Some(level), format!("Expand to level {}", level)).clicked() {
+							self.view.depth_limit = Some(level);
+							ui.close_menu();
+						}
+					}
+					ui.separator();
+					ui.radio_value(&mut self.view.layout, Layout::BreadthFirst, "Fill breadth first");
+					ui.radio_value(&mut self.view.layout, Layout::DepthFirst, "Outline depth first");
+				});
+				let focus_icon = if self.tree.focus { "󰋱" } else { "󰋲" };
+				if ui.button(focus_icon).clicked() { self.tree.focus = !self.tree.focus }
+				let hide_done_icon = if self.tree.hide_done { "󰛑" } else { "󰛐" };
+				if ui.button(hide_done_icon).clicked() { self.tree.hide_done = !self.tree.hide_done }
+				ui.menu_button("󰒆", |ui| {
+					if ui.button("Select all visible").clicked() {
+						action = Action::SelectVisible;
+						ui.close_menu();
+					}
+					if ui.button("Invert").clicked() {
+						action = Action::InvertSelection;
+						ui.close_menu();
+					}
+					if ui.button("Clear").clicked() {
+						action = Action::ClearSelection;
+						ui.close_menu();
+					}
+				});
+				ui.menu_button("󰒺", |ui| {
+					let id = match self.tree.selection_ids().collect::<Vec<_>>()[..] {
+						[&id] => id,
+						_ => self.root_id,
+					};
+					let current = self.tree.sort_mode(id);
+					for mode in SortMode::ALL {
+						if ui.radio(current == mode, mode.label()).clicked() {
+							action = Action::Sort(id, mode);
+							ui.close_menu();
+						}
+					}
+					ui.separator();
+					if ui.add_enabled(current != SortMode::Manual, Button::new("Keep as manual order")).clicked() {
+						action = Action::PersistOrder(id, current);
+						ui.close_menu();
+					}
+				});
+				if ui.button("󰉵").clicked() { action = Action::Outdent }
+				if ui.button("󰉶").clicked() { action = Action::Indent }
+				if ui.button("󰸕").clicked() { self.todo = Action::ListSelectionSets }
+				if ui.button("󰙅").clicked() { self.todo = Action::ListTemplates }
+				ui.menu_button("󰄸", |ui| {
+					if ui.button("Delete").clicked() {
+						if self.confirm_delete {
+							self.todo = Action::Bulk(Bulk::Delete);
+						} else {
+							action = Action::Bulk(Bulk::Delete);
+						}
+						ui.close_menu();
+					}
+					if ui.button("Move").clicked() {
+						self.todo = Action::PickParent(Bulk::Move);
+						ui.close_menu();
+					}
+					if ui.button("Link").clicked() {
+						self.todo = Action::PickParent(Bulk::Link);
+						ui.close_menu();
+					}
+					if ui.button("Complete").clicked() {
+						action = Action::Bulk(Bulk::Complete);
+						ui.close_menu();
+					}
+					if ui.add_enabled(self.tree.selection_ids().count() == 2, Button::new("Merge")).clicked() {
+						action = Action::MergeOptions;
+						ui.close_menu();
+					}
+					if ui.add_enabled(self.tree.selection_count() == 1, Button::new("Split")).clicked() {
+						if let Some((&pid, &id)) = self.tree.selections().next() {
+							self.vboard_text = self.tree.node_at(id).name.clone();
+							self.todo = Action::Split(pid, id);
+						}
+						ui.close_menu();
+					}
+					if ui.button("Duplicate").clicked() {
+						self.todo = Action::DuplicateOptions;
+						ui.close_menu();
+					}
+					if ui.button("Clear due date").clicked() {
+						action = Action::Bulk(Bulk::ClearDueDate);
+						ui.close_menu();
+					}
+				});
+				ui.label(format!("{} selected", self.tree.selection_count()));
+				ui.label(&self.status);
 			});
 		});
 
+		if let Action::Add(pid, id) = self.todo {
+			let pasted = ctx.input(|i| i.events.iter().find_map(|event| match event {
+				Event::Paste(text) if text.trim().contains('\n') => Some(outline::parse(text)),
+				_ => None,
+			}));
+			if let Some(pasted) = pasted {
+				self.outline = pasted;
+				self.todo = Action::PasteOutline(pid, id);
+			}
+		}
+
 		let show_vboard = self.todo != Action::None;
 		TopBottomPanel::bottom("vboard").show_animated(ctx, show_vboard, |ui| {
 			match self.todo {
-				Action::Add(_, _) | Action::Rename => {
-					let mut output = ui.horizontal(|ui| {
+				Action::Add(_, _) | Action::Rename | Action::SaveSelection | Action::SaveTemplate | Action::Split(..) => {
+					let splitting = matches!(self.todo, Action::Split(..));
+					let response = ui.horizontal(|ui| {
 						if ui.button("<-").clicked() {
 							self.todo = Action::None;
 							self.tree.highlighted = None;
 							self.vboard_text.clear();
 						}
+						if splitting {
+							ui.checkbox(&mut self.split_children, "As children");
+							if ui.button("Split").clicked() {
+								action = self.todo;
+								self.todo = Action::None;
+							}
+						}
 						TextEdit::singleline(&mut self.vboard_text)
 							.desired_width(f32::INFINITY)
 							.show(ui)
+							.response
 					}).inner;
-					let res = if self.vboard_caps {
-						ui.caps_vboard()
-					} else {
-						ui.vboard()
-					};
-					if let Some(key) = res {
-						output.response.request_focus();
-						match key {
-							Key::Char(c) => {
-								if let Some(ccursor_range) = output.state.ccursor_range() {
-									let mut ccursor = delete_selected(&mut self.vboard_text, &ccursor_range);
-									insert_char(&mut ccursor, &mut self.vboard_text, c);
-									output.state.set_ccursor_range(Some(CCursorRange::one(ccursor)));
-									output.state.store(ctx, output.response.id);
-								} else {
-									self.vboard_text.push(c);
-								}
-							}
-							Key::Enter => {
-								action = self.todo;
-								self.todo = Action::None;
-								self.tree.highlighted = None;
-							}
-							Key::Backspace => {
-								if let Some(ccursor_range) = output.state.ccursor_range() {
-									let ccursor = if ccursor_range.primary == ccursor_range.secondary {
-										delete_previous_char(&mut self.vboard_text, ccursor_range.primary)
-									} else {
-										delete_selected(&mut self.vboard_text, &ccursor_range)
-									};
-									output.state.set_ccursor_range(Some(CCursorRange::one(ccursor)));
-									output.state.store(ctx, output.response.id);
-								}
-							}
-							Key::CapsLock => {
-								self.vboard_caps = !self.vboard_caps;
-							}
+					if splitting {
+						for part in split_list(&self.vboard_text) {
+							ui.label(format!("• {}", part));
 						}
 					}
+					if vboard_input(ui, &mut self.vboard_caps, &mut self.vboard_text, Some(response.id)) {
+						action = self.todo;
+						self.todo = Action::None;
+						self.tree.highlighted = None;
+					}
 				}
 				Action::SetDueDate => {
 					ui.horizontal(|ui| {
@@ -275,19 +1047,388 @@ impl App for Grus {
 					});
 					ui.add_space(200.);
 				}
+				Action::ListSeries => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						if ui.add_enabled(self.tree.selection_count() > 0, Button::new("New")).clicked() {
+							self.start_date = Local::now().naive_local();
+							self.end_date = Local::now().naive_local();
+							self.until_date = Local::now().naive_local();
+							self.todo = Action::AddSeries;
+						}
+					});
+					for series in self.series.iter() {
+						ui.horizontal(|ui| {
+							if ui.button(" 🗑 ").clicked() {
+								action = Action::DeleteSeries(series.id);
+							}
+							if ui.button("󰏫").clicked() {
+								self.start_date = series.first.start;
+								self.end_date = series.first.end;
+								self.until_date = series.until;
+								self.todo = Action::EditSeries(series.id);
+							}
+							ui.label(format!("{} ({} tasks)", series, series.nodes.len()));
+						});
+					}
+				}
+				Action::Delete(..) | Action::Bulk(Bulk::Delete) => {
+					let impact = if let Action::Delete(pid, id) = self.todo {
+						ui.label(format!("Delete \"{}\"?", self.tree.node_at(id).name));
+						self.tree.delete_impact(pid, id)
+					} else {
+						ui.label(format!("Delete {} selected tasks?", delete_targets.len()));
+						self.tree.bulk_delete_impact(&delete_targets)
+					};
+					if impact.removed == 0 && matches!(self.todo, Action::Delete(..)) {
+						ui.label("It is shared with other parents and will only be unlinked from here.");
+					} else if impact.removed == 0 {
+						ui.label("They are shared with other parents and will only be unlinked from here.");
+					} else {
+						ui.label(format!(
+							"{} tasks will be removed along with {} sessions and {} due dates.",
+							impact.removed,
+							impact.sessions,
+							impact.due_dates,
+						));
+					}
+					if impact.shared > 0 {
+						ui.label(format!("{} shared tasks have other parents and will be kept.", impact.shared));
+					}
+					let mut dont_ask = !self.confirm_delete;
+					ui.checkbox(&mut dont_ask, "Don't ask again this session");
+					self.confirm_delete = !dont_ask;
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+							self.tree.highlighted = None;
+						}
+						if ui.button("Delete").clicked() {
+							action = self.todo;
+							self.todo = Action::None;
+							self.tree.highlighted = None;
+						}
+					});
+				}
+				Action::PasteOutline(..) => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.outline.clear();
+							self.todo = Action::None;
+							self.tree.highlighted = None;
+						}
+						if ui.button("Add").clicked() {
+							action = self.todo;
+							self.todo = Action::None;
+							self.tree.highlighted = None;
+						}
+					});
+					for snapshot in &self.outline {
+						outline_preview(ui, &self.outline, snapshot, 0);
+					}
+				}
+				Action::BulkRename => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+							self.rename_pattern.clear();
+						}
+						if ui.button("Rename").clicked() {
+							action = Action::BulkRename;
+							self.todo = Action::None;
+						}
+						ui.checkbox(&mut self.rename_pattern.regex, "Regex");
+					});
+					let mut field_ids = Vec::new();
+					for (field, hint) in [
+						(PatternField::Find, "Find"),
+						(PatternField::Replace, "Replace with, {n} and {name} allowed"),
+						(PatternField::Prefix, "Prefix"),
+						(PatternField::Suffix, "Suffix"),
+					] {
+						let response = TextEdit::singleline(self.rename_pattern.field_mut(field))
+							.hint_text(hint)
+							.desired_width(f32::INFINITY)
+							.show(ui)
+							.response;
+						if response.has_focus() {
+							self.rename_pattern.focused = field;
+						}
+						field_ids.push((field, response.id));
+					}
+					match self.rename_pattern.rename_all(rename_ids.iter().map(|&id| self.tree.node_at(id).name.as_str())) {
+						Ok(names) => for (id, name) in rename_ids.iter().zip(names) {
+							ui.label(format!("{} → {}", self.tree.node_at(*id).name, name));
+						}
+						Err(err) => _ = ui.label(format!("{}", err)),
+					}
+					#[cfg(target_os = "android")]
+					{
+						let focused = self.rename_pattern.focused;
+						let id = field_ids.iter().find(|&&(field, _)| field == focused).map(|&(_, id)| id);
+						if vboard_input(ui, &mut self.vboard_caps, self.rename_pattern.field_mut(focused), id) {
+							action = Action::BulkRename;
+							self.todo = Action::None;
+						}
+					}
+				}
+				Action::EditName(..) => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							action = Action::CancelRename;
+						}
+						if ui.button("Rename").clicked() {
+							action = Action::CommitRename;
+						}
+					});
+					#[cfg(target_os = "android")]
+					if vboard_input(ui, &mut self.vboard_caps, &mut self.view.edit_text, self.view.edit_id) {
+						action = Action::CommitRename;
+					}
+				}
+				Action::MergeOptions => if let Some(merge) = &mut self.merge {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						if ui.button("󰓡").clicked() {
+							mem::swap(&mut merge.into, &mut merge.from);
+						}
+						if ui.button("Merge").clicked() {
+							action = Action::Merge;
+							self.todo = Action::None;
+						}
+					});
+					ui.label(format!(
+						"Merge \"{}\" into \"{}\"",
+						self.tree.node_at(merge.from).name,
+						self.tree.node_at(merge.into).name,
+					));
+					ui.horizontal(|ui| {
+						ui.label("Keep due date");
+						ui.radio_value(&mut merge.due, MergeDue::Earlier, "Earlier");
+						ui.radio_value(&mut merge.due, MergeDue::First, "Kept task's");
+						ui.radio_value(&mut merge.due, MergeDue::Second, "Merged task's");
+					});
+				}
+				Action::DuplicateOptions => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						if ui.button("Here").clicked() {
+							action = Action::Bulk(Bulk::Duplicate);
+							self.todo = Action::None;
+						}
+						if ui.button("Elsewhere").clicked() {
+							self.todo = Action::PickParent(Bulk::Duplicate);
+						}
+					});
+					ui.horizontal(|ui| {
+						ui.checkbox(&mut self.duplicate_options.sessions, "Include sessions");
+						ui.label("Shift dates by");
+						ui.add(DragValue::new(&mut self.duplicate_options.shift_days));
+						ui.label("days");
+					});
+				}
+				Action::PickParent(bulk) => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						let verb = match bulk {
+							Bulk::Move => "move",
+							Bulk::Duplicate => "copy",
+							_ => "link",
+						};
+						if bulk == Bulk::Instantiate {
+							ui.label("Tap + on the task to add the template under");
+						} else {
+							ui.label(format!("Tap + on the task to {} {} selected tasks under", verb, self.tree.selections().count()));
+						}
+					});
+				}
+				Action::ListTemplates => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						let save = ui.add_enabled(self.tree.selection_count() == 1, Button::new("Save selected"));
+						if save.clicked() {
+							self.todo = Action::SaveTemplate;
+						}
+					});
+					for (i, template) in self.templates.iter().enumerate() {
+						ui.horizontal(|ui| {
+							if ui.button(" 🗑 ").clicked() {
+								action = Action::DeleteTemplate(i);
+							}
+							if ui.button("Use").clicked() {
+								action = Action::UseTemplate(i);
+							}
+							ui.label(format!("{} ({} tasks)", template.name, template.snapshot.node_count()));
+						});
+					}
+				}
+				Action::InstantiateTemplate => if let Some(draft) = &mut self.template_draft {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::ListTemplates;
+						}
+						ui.label("Anchor");
+						ui.add(DatePicker::<Range<NaiveDateTime>>::new(
+							"anchordate",
+							&mut draft.anchor,
+						));
+						if ui.button("Pick parent").clicked() {
+							self.todo = Action::PickParent(Bulk::Instantiate);
+						}
+					});
+					let mut value_ids = Vec::new();
+					for (i, (placeholder, value)) in draft.values.iter_mut().enumerate() {
+						let response = TextEdit::singleline(value)
+							.hint_text(placeholder.as_str())
+							.desired_width(f32::INFINITY)
+							.show(ui)
+							.response;
+						if response.has_focus() {
+							draft.focused = i;
+						}
+						value_ids.push(response.id);
+					}
+					#[cfg(target_os = "android")]
+					if let Some((_, text)) = draft.values.get_mut(draft.focused) {
+						vboard_input(ui, &mut self.vboard_caps, text, value_ids.get(draft.focused).copied());
+					}
+					ui.add_space(200.);
+				}
+				Action::ListSelectionSets => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						if ui.button("Save selection").clicked() {
+							self.todo = Action::SaveSelection;
+						}
+					});
+					for (i, set) in self.selection_sets.iter().enumerate() {
+						ui.horizontal(|ui| {
+							if ui.button(" 🗑 ").clicked() {
+								action = Action::SelectionSet(i, SetOp::Delete);
+							}
+							if ui.button("Restore").clicked() {
+								action = Action::SelectionSet(i, SetOp::Restore);
+							}
+							if ui.button("󰝸").clicked() {
+								action = Action::SelectionSet(i, SetOp::Union);
+							}
+							if ui.button("󰝹").clicked() {
+								action = Action::SelectionSet(i, SetOp::Intersection);
+							}
+							ui.label(format!("{} ({} tasks)", set.name, set.selections.len()));
+						});
+					}
+				}
+				Action::ListTrash => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::None;
+						}
+						let mut days = self.trash.retention_days;
+						ui.label("Keep for");
+						if ui.add(DragValue::new(&mut days).clamp_range(1..=365)).changed() {
+							action = Action::SetRetention(days);
+						}
+						ui.label("days");
+					});
+					for (i, entry) in self.trash.iter().enumerate().rev() {
+						ui.horizontal(|ui| {
+							if ui.button("󰗨").clicked() {
+								action = Action::Purge(i);
+							}
+							if ui.button("󰠙").clicked() {
+								action = Action::Restore(i);
+							}
+							ui.label(format!(
+								"{} ({} tasks) deleted {}",
+								entry.snapshot.name(&self.tree),
+								entry.snapshot.node_count(),
+								Displayable(Some(entry.deleted_at)),
+							));
+						});
+					}
+				}
+				Action::AddSeries | Action::EditSeries(_) => {
+					ui.horizontal(|ui| {
+						if ui.button("<-").clicked() {
+							self.todo = Action::ListSeries;
+						}
+						ui.add(DatePicker::<Range<NaiveDateTime>>::new(
+							"startdate",
+							&mut self.start_date,
+						));
+						ui.add(DatePicker::<Range<NaiveDateTime>>::new(
+							"enddate",
+							&mut self.end_date,
+						));
+						ui.label("until");
+						ui.add(DatePicker::<Range<NaiveDateTime>>::new(
+							"untildate",
+							&mut self.until_date,
+						));
+						if ui.button("Set").clicked() {
+							action = self.todo;
+							self.todo = Action::ListSeries;
+						}
+					});
+					ui.add_space(200.);
+				}
 				_ => unreachable!(),
 			}
 			ui.add_space(30.0);
 		});
 
 		CentralPanel::default().show(ctx, |ui| {
-			match ui.flattree(&self.tree, self.root_pid, self.root_id) {
-				Action::Add(pid, id) => {
-					self.todo = Action::Add(pid, id);
+			if self.next_view {
+				let actions = self.next_actions.get(&self.tree, self.root_pid, self.root_id, &self.urgency, Local::now().naive_local());
+				match ui.next_actions_view(&self.tree, actions, &mut self.urgency) {
+					Action::MoveInto(pid, id) => {
+						self.next_view = false;
+						if (pid, id) != (self.root_pid, self.root_id) {
+							action = Action::MoveInto(pid, id);
+						}
+					}
+					Action::ToggleDone(id) => action = Action::ToggleDone(id),
+					Action::SaveUrgency => action = Action::SaveUrgency,
+					_ => {}
+				}
+				return;
+			}
+			match ui.flattree(&self.tree, self.root_pid, self.root_id, &mut self.view) {
+				Action::Add(pid, id) => match self.todo {
+					Action::PickParent(bulk) => {
+						action = Action::BulkTo(bulk, id);
+						self.todo = Action::None;
+					}
+					_ => {
+						self.todo = Action::Add(pid, id);
+						self.tree.highlighted = Some(id);
+					}
+				}
+				Action::Delete(pid, id) if self.confirm_delete => {
+					self.todo = Action::Delete(pid, id);
 					self.tree.highlighted = Some(id);
 				}
 				Action::Delete(pid, id) => action = Action::Delete(pid, id),
 				Action::Toggle(pid, id) => action = Action::Toggle(pid, id),
+				Action::SelectRange(pid, id) => action = Action::SelectRange(pid, id),
+				Action::SelectSubtree(pid, id) => action = Action::SelectSubtree(pid, id),
+				Action::EditName(pid, id) => action = Action::EditName(pid, id),
+				Action::CommitRename => action = Action::CommitRename,
+				Action::CancelRename => action = Action::CancelRename,
+				Action::ToggleDone(id) => action = Action::ToggleDone(id),
 				Action::MoveInto(pid, id) => action = Action::MoveInto(pid, id),
 				Action::MoveOut => action = Action::MoveOut,
 				_ => {}
@@ -302,10 +1443,56 @@ impl App for Grus {
 pub enum Action {
 	Add(u64, u64),
 	Delete(u64, u64),
+	Copy(u64),
+	Cut(u64, u64),
+	PasteCut(u64),
+	PasteOutline(u64, u64),
+	Split(u64, u64),
 	Rename,
+	BulkRename,
+	EditName(u64, u64),
+	CommitRename,
+	CancelRename,
 	SetDueDate,
 	AddSession,
+	ListSeries,
+	AddSeries,
+	EditSeries(u64),
+	DeleteSeries(u64),
+	ListTrash,
+	Restore(usize),
+	Purge(usize),
+	SetRetention(u32),
+	Archive,
+	SwitchRoot(u64),
+	Bulk(Bulk),
+	PickParent(Bulk),
+	BulkTo(Bulk, u64),
+	DuplicateOptions,
+	MergeOptions,
+	Merge,
+	ListTemplates,
+	SaveTemplate,
+	UseTemplate(usize),
+	DeleteTemplate(usize),
+	InstantiateTemplate,
+	Indent,
+	Outdent,
+	Undo,
+	Redo,
 	Toggle(u64, u64),
+	SelectRange(u64, u64),
+	SelectSubtree(u64, u64),
+	ListSelectionSets,
+	SaveSelection,
+	SelectionSet(usize, SetOp),
+	SelectVisible,
+	InvertSelection,
+	ClearSelection,
+	Sort(u64, SortMode),
+	SaveUrgency,
+	PersistOrder(u64, SortMode),
+	ToggleDone(u64),
 	MoveInto(u64, u64),
 	MoveOut,
 	Import,
@@ -313,15 +1500,118 @@ pub enum Action {
 	None,
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum Bulk {
+	Delete,
+	Move,
+	Link,
+	Complete,
+	ClearDueDate,
+	Duplicate,
+	Instantiate,
+}
+
+struct MergeDraft {
+	into: u64,
+	from: u64,
+	due: MergeDue,
+}
+
+#[derive(PartialEq)]
+enum MergeDue {
+	Earlier,
+	First,
+	Second,
+}
+
+struct TemplateDraft {
+	index: usize,
+	values: Vec<(String, String)>,
+	anchor: NaiveDateTime,
+	focused: usize,
+}
+
+struct DuplicateOptions {
+	sessions: bool,
+	shift_days: i64,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SetOp {
+	Restore,
+	Union,
+	Intersection,
+	Delete,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("Store Error: {0}")]
 	StoreError(#[from] grus_lib::Error),
+	#[error("IO Error: {0}")]
+	IoError(#[from] io::Error),
 	#[cfg(target_os = "android")]
 	#[error("JNI Error: {0}")]
 	JniError(#[from] jni::errors::Error),
 }
 
+fn split_list(text: &str) -> impl Iterator<Item = &str> {
+	text.split(['\n', ',']).map(str::trim).filter(|part| !part.is_empty())
+}
+
+// Subtrees an applied delete took out of the store, as opposed to links it only dropped.
+fn removed_subtrees(inverse: &[Change]) -> Vec<(u64, Snapshot)> {
+	inverse.iter().filter_map(|change| match change {
+		Change::Insert { pid, snapshot: snapshot @ Snapshot::Node { .. } } => Some((*pid, snapshot.clone())),
+		_ => None,
+	}).collect()
+}
+
+fn outline_preview(ui: &mut Ui, outline: &[Snapshot], snapshot: &Snapshot, depth: usize) {
+	ui.horizontal(|ui| {
+		ui.add_space(depth as f32 * INDENT_SPACING);
+		match snapshot {
+			Snapshot::Node { name, .. } => ui.label(name),
+			Snapshot::Link(id) => {
+				let name = outline.iter().find_map(|root| root.node_name(*id)).unwrap_or_default();
+				ui.label(format!("↪ {}", name))
+			}
+		};
+	});
+	let Snapshot::Node { children, .. } = snapshot else { return };
+	for child in children {
+		outline_preview(ui, outline, child, depth + 1);
+	}
+}
+
+// Types a virtual keyboard key into the text edit `id` at its cursor, returning whether Enter was pressed.
+fn vboard_input(ui: &mut Ui, caps: &mut bool, text: &mut String, id: Option<Id>) -> bool {
+	let key = if *caps { ui.caps_vboard() } else { ui.vboard() };
+	let Some(key) = key else { return false };
+	let mut state = id.and_then(|id| TextEditState::load(ui.ctx(), id)).unwrap_or_default();
+	let ccursor_range = state.ccursor_range().unwrap_or_else(|| CCursorRange::one(CCursor::new(text.chars().count())));
+	let ccursor = match key {
+		Key::Char(c) => {
+			let mut ccursor = delete_selected(text, &ccursor_range);
+			insert_char(&mut ccursor, text, c);
+			ccursor
+		}
+		Key::Backspace if ccursor_range.primary == ccursor_range.secondary => delete_previous_char(text, ccursor_range.primary),
+		Key::Backspace => delete_selected(text, &ccursor_range),
+		Key::CapsLock => {
+			*caps = !*caps;
+			return false;
+		}
+		Key::Enter => return true,
+	};
+	if let Some(id) = id {
+		state.set_ccursor_range(Some(CCursorRange::one(ccursor)));
+		state.store(ui.ctx(), id);
+		ui.memory_mut(|memory| memory.request_focus(id));
+	}
+	false
+}
+
 fn insert_char(
 	ccursor: &mut CCursor,
 	text: &mut dyn TextBuffer,
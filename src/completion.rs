@@ -0,0 +1,73 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDateTime;
+use crate::sidecar::{self, format_datetime, invalid_data, parse_datetime, parse_id};
+
+#[derive(Default)]
+pub struct Completions {
+	path: PathBuf,
+	done: HashMap<u64, NaiveDateTime>,
+}
+
+impl Completions {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "done");
+		let mut done = HashMap::new();
+		for record in sidecar::read_records(&path)? {
+			let [id, at] = &record[..] else { return Err(invalid_data(&path)) };
+			done.insert(parse_id(&path, id)?, parse_datetime(&path, at)?);
+		}
+		Ok(Completions { path, done })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		sidecar::write_records(&self.path, self.done.iter().map(|(id, &at)| [
+			id.to_string(),
+			format_datetime(at),
+		]))
+	}
+
+	pub fn get(&self, id: u64) -> Option<NaiveDateTime> {
+		self.done.get(&id).copied()
+	}
+
+	pub fn set(&mut self, id: u64, at: Option<NaiveDateTime>) {
+		match at {
+			Some(at) => self.done.insert(id, at),
+			None => self.done.remove(&id),
+		};
+	}
+
+	pub fn retain(&mut self, mut f: impl FnMut(u64) -> bool) {
+		self.done.retain(|&id, _| f(id));
+	}
+
+	pub fn clear(&mut self) {
+		self.done.clear();
+	}
+
+	pub fn rekey(&mut self, ids: &HashMap<u64, u64>) {
+		for (old_id, &id) in ids {
+			if let Some(at) = self.done.remove(old_id) {
+				self.done.insert(id, at);
+			}
+		}
+	}
+}
@@ -14,28 +14,53 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
+use std::time::Duration;
 use std::vec::IntoIter;
-use egui::{Color32, Pos2, RichText, Sense, Ui};
+use egui::{Color32, Id, Key, Pos2, Rect, RichText, Sense, TextEdit, Ui, Vec2};
 use grus_gui_lib::{Button, Create, Checkbox, ExtLayout, Label, LaidOutButton, LaidOutCheckbox, LaidOutLabel, Paint, WidgetPlacer};
 use crate::app::Action;
-use crate::node::{Displayable, Node, Tree};
+use crate::node::{Displayable, Node, Progress, Tree};
+
+pub const INDENT_SPACING: f32 = 14.0;
+const EDIT_WIDTH: f32 = 150.0;
+// Matches egui's own double click window, so a single click only navigates once a second one can't follow.
+const DOUBLE_CLICK_DELAY: f64 = 0.3;
+
+#[derive(Default)]
+pub struct ViewState {
+	pub rows: Vec<(u64, u64)>,
+	pub hovered: Option<(u64, u64)>,
+	pub editing: Option<(u64, u64)>,
+	pub edit_text: String,
+	pub edit_id: Option<Id>,
+	pub focus_edit: bool,
+	pub depth_limit: Option<usize>,
+	pub layout: Layout,
+	pub pending_open: Option<(u64, u64, f64)>,
+}
 
-const INDENT_SPACING: f32 = 14.0;
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Layout {
+	#[default]
+	BreadthFirst,
+	DepthFirst,
+}
 
 pub trait FlatTree {
-	fn flattree(&mut self, tree: &Tree, pid: u64, id: u64) -> Action;
+	fn flattree(&mut self, tree: &Tree, pid: u64, id: u64, view: &mut ViewState) -> Action;
 }
 
 impl FlatTree for Ui {
-	fn flattree(&mut self, tree: &Tree, pid: u64, id: u64) -> Action {
+	fn flattree(&mut self, tree: &Tree, pid: u64, id: u64, view: &mut ViewState) -> Action {
+		view.rows.clear();
+		view.hovered = None;
 		let mut wp = WidgetPlacer::new(&self);
 		let mut lofnodes = Vec::new();
-		let mut queue = VecDeque::new();
-		let mut start = 0;
 		let maxy = self.available_rect_before_wrap().bottom();
+		let focus = tree.focus_ancestors();
 
 		let root = FNode {
 			node: tree.node_at(id),
@@ -43,49 +68,147 @@ impl FlatTree for Ui {
 			pid,
 			depth: 0,
 			selected: tree.is_selected(pid, id),
+			progress: tree.progress(id),
 			priority: Priority { det: 0, total: 1 },
+			hidden: Hidden::default(),
 		};
 
 		let lofnode = create_fnode(&mut wp, root, tree.highlighted.is_some_and(|h| h == id));
 		if wp.next_widget_position().y > maxy { return Action::None };
 		lofnodes.push(lofnode);
 
-		'outer: loop {
-			for i in start..lofnodes.len() {
-				queue.push_back(FChildIter::new(&lofnodes[i].fnode, tree));
-			}
-			start = lofnodes.len();
-
-			while let Some(mut children) = queue.pop_front() {
-				let Some(mut child) = children.iter.next() else { continue };
-				child.path.push(lofnodes.len());
-				let id = child.node.id;
-				let lofnode = create_fnode(&mut wp, child, tree.highlighted.is_some_and(|h| h == id));
-				if wp.next_widget_position().y > maxy { break 'outer }
-				queue.push_back(children);
-				lofnodes.push(lofnode);
-			}
-			if start == lofnodes.len() { break };
+		match view.layout {
+			Layout::BreadthFirst => layout_breadth_first(&mut wp, tree, &mut lofnodes, maxy, focus.as_ref(), view.depth_limit),
+			Layout::DepthFirst => layout_depth_first(&mut wp, tree, &mut lofnodes, maxy, focus.as_ref(), view.depth_limit),
+		}
+
+		// Badges depend on which rows made it into the view, so rows that need one are laid out again.
+		// A badge can wrap its row onto more lines, so the last rows give way until everything fits.
+		let top = self.next_widget_position().y;
+		let spacing = self.spacing().item_spacing.y;
+		loop {
+			let hidden = hidden_descendants(&lofnodes, tree, focus.as_ref());
+			lofnodes = lofnodes.into_iter().zip(hidden).map(|(lofnode, hidden)| {
+				if hidden == lofnode.fnode.hidden { return lofnode }
+				let id = lofnode.fnode.node.id;
+				let mut fnode = lofnode.fnode;
+				fnode.hidden = hidden;
+				create_fnode(&mut wp, fnode, tree.highlighted.is_some_and(|h| h == id))
+			}).collect();
+			let height: f32 = lofnodes.iter().map(|lofnode| lofnode.height(spacing)).sum();
+			if top + height <= maxy || lofnodes.len() == 1 { break }
+			lofnodes.pop();
 		}
 
 		lofnodes.sort_by(|l, r| l.fnode.path.cmp(&r.fnode.path));
+		view.rows.extend(lofnodes.iter().skip(1).map(|lofnode| (lofnode.fnode.pid, lofnode.fnode.node.id)));
 
 		let mut tvp = TreeViewPainter::new(self, &mut lofnodes);
-		tvp.place_fnodes();
+		tvp.place_fnodes(view);
 		tvp.paint_div_lines();
-		tvp.action
+		let action = tvp.action;
+
+		if let Some((pid, id, clicked_at)) = view.pending_open {
+			let elapsed = self.input(|i| i.time) - clicked_at;
+			if elapsed >= DOUBLE_CLICK_DELAY {
+				view.pending_open = None;
+				if action == Action::None && view.rows.contains(&(pid, id)) {
+					return Action::MoveInto(pid, id);
+				}
+			} else {
+				self.ctx().request_repaint_after(Duration::from_secs_f64(DOUBLE_CLICK_DELAY - elapsed));
+			}
+		}
+		action
+	}
+}
+
+// Fills the space level by level, taking one child from each parent in turn.
+fn layout_breadth_first<'node>(
+	wp: &mut WidgetPlacer,
+	tree: &'node Tree,
+	lofnodes: &mut Vec<LaidOutFNode<'node>>,
+	maxy: f32,
+	focus: Option<&HashSet<u64>>,
+	depth_limit: Option<usize>,
+) {
+	let mut queue = VecDeque::new();
+	let mut start = 0;
+	loop {
+		for i in start..lofnodes.len() {
+			if depth_limit.is_some_and(|limit| lofnodes[i].fnode.depth >= limit) { continue }
+			queue.push_back(FChildIter::new(&lofnodes[i].fnode, tree, focus));
+		}
+		start = lofnodes.len();
+
+		while let Some(mut children) = queue.pop_front() {
+			let Some(mut child) = children.iter.next() else { continue };
+			child.path.push(lofnodes.len());
+			let id = child.node.id;
+			let lofnode = create_fnode(wp, child, tree.highlighted.is_some_and(|h| h == id));
+			if wp.next_widget_position().y > maxy { return }
+			queue.push_back(children);
+			lofnodes.push(lofnode);
+		}
+		if start == lofnodes.len() { return };
+	}
+}
+
+// Lays out a plain outline, so the visible rows only grow or shrink at the bottom as space changes.
+fn layout_depth_first<'node>(
+	wp: &mut WidgetPlacer,
+	tree: &'node Tree,
+	lofnodes: &mut Vec<LaidOutFNode<'node>>,
+	maxy: f32,
+	focus: Option<&HashSet<u64>>,
+	depth_limit: Option<usize>,
+) {
+	let mut stack = Vec::new();
+	if !depth_limit.is_some_and(|limit| lofnodes[0].fnode.depth >= limit) {
+		stack.push(FChildIter::new(&lofnodes[0].fnode, tree, focus));
+	}
+	while let Some(children) = stack.last_mut() {
+		let Some(mut child) = children.iter.next() else {
+			stack.pop();
+			continue;
+		};
+		child.path.push(lofnodes.len());
+		let id = child.node.id;
+		let lofnode = create_fnode(wp, child, tree.highlighted.is_some_and(|h| h == id));
+		if wp.next_widget_position().y > maxy { return }
+		let expand = !depth_limit.is_some_and(|limit| lofnode.fnode.depth >= limit);
+		lofnodes.push(lofnode);
+		if expand {
+			stack.push(FChildIter::new(&lofnodes[lofnodes.len() - 1].fnode, tree, focus));
+		}
 	}
 }
 
 fn create_fnode<'node>(wp: &mut WidgetPlacer, fnode: FNode<'node>, highlighted: bool) -> LaidOutFNode<'node> {
-	let label_text = if highlighted {
+	let mut label_text = if highlighted {
 		RichText::new(&fnode.node.name).color(Color32::YELLOW)
 	} else {
 		RichText::new(&fnode.node.name)
 	};
-	let ((checkbox, text, add_button, del_button), rect1) = wp.right_to_left(|wp| {
+	if fnode.node.done.is_some() {
+		label_text = label_text.strikethrough().weak();
+	}
+	let done_text = if fnode.node.done.is_some() { " 󰗠 " } else { " 󰝦 " };
+	let ((checkbox, text, progress_label, hidden_button, done_button, add_button, del_button), rect1) = wp.right_to_left(|wp| {
 		let del_button = wp.create(Button::new(" 🗑 "));
 		let add_button = wp.create(Button::new(" + "));
+		let done_button = wp.create(Button::new(done_text));
+		let progress_label = fnode.progress.map(|progress| {
+			wp.create(Label::new(RichText::new(format!("{}/{}", progress.done, progress.total)).color(color_from_progress(&progress))))
+		});
+		let hidden_button = (fnode.hidden.direct > 0).then(|| {
+			let hidden = if fnode.hidden.direct == fnode.hidden.total {
+				format!("+{} hidden", fnode.hidden.direct)
+			} else {
+				format!("+{} · {} hidden", fnode.hidden.direct, fnode.hidden.total)
+			};
+			wp.create(Button::new(RichText::new(hidden).small().weak()))
+		});
 		let ((checkbox, text), _) = wp.left_to_right(|wp| {
 			wp.add_space(INDENT_SPACING * fnode.depth as f32);
 			(
@@ -93,27 +216,35 @@ fn create_fnode<'node>(wp: &mut WidgetPlacer, fnode: FNode<'node>, highlighted:
 				wp.create(Label::new(label_text).wrap(true).sense(Sense::click())),
 			)
 		});
-		(checkbox, text, add_button, del_button)
+		(checkbox, text, progress_label, hidden_button, done_button, add_button, del_button)
 	});
 
-	if fnode.node.session.is_none() && fnode.node.due_date.is_none() {
+	if fnode.node.session.is_none() && fnode.node.due_date.is_none() && fnode.node.done.is_none() {
 		return LaidOutFNode {
 			fnode,
 			checkbox,
 			text,
+			progress_label,
+			hidden_button,
+			done_button,
 			add_button,
 			del_button,
 			session_label: None,
 			due_date_label: None,
+			done_label: None,
 			height1: rect1.height(),
 			height2: 0.,
 		};
 	}
-	let ((session_label, due_date_label), rect2) = wp.right_to_left(|wp| {
+	let ((session_label, due_date_label, done_label), rect2) = wp.right_to_left(|wp| {
 		let due_date_label = fnode.node.due_date.map(|due_date| {
 			let due_date = format!("{}", Displayable(Some(due_date)));
 			wp.create(Label::new(due_date))
 		});
+		let done_label = fnode.node.done.map(|done| {
+			let done = format!("󰄬 {}", Displayable(Some(done)));
+			wp.create(Label::new(RichText::new(done).weak()))
+		});
 		let (session_label, _) = wp.left_to_right(|wp| {
 			wp.add_space(INDENT_SPACING * fnode.depth as f32);
 			fnode.node.session.map(|session| {
@@ -121,9 +252,23 @@ fn create_fnode<'node>(wp: &mut WidgetPlacer, fnode: FNode<'node>, highlighted:
 				wp.create(Label::new(session).wrap(true))
 			})
 		});
-		(session_label, due_date_label)
+		(session_label, due_date_label, done_label)
 	});
-	LaidOutFNode { fnode, checkbox, text, add_button, del_button, session_label, due_date_label, height1: rect1.height(), height2: rect2.height() }
+	LaidOutFNode {
+		fnode,
+		checkbox,
+		text,
+		progress_label,
+		hidden_button,
+		done_button,
+		add_button,
+		del_button,
+		session_label,
+		due_date_label,
+		done_label,
+		height1: rect1.height(),
+		height2: rect2.height(),
+	}
 }
 
 struct TreeViewPainter<'ui, 'lofnodes, 'node> {
@@ -155,24 +300,82 @@ impl<'ui, 'lofnodes, 'node> TreeViewPainter<'ui, 'lofnodes, 'node> {
 		TreeViewPainter { ui, lofnodes, maxy, color_map, action: Action::None }
 	}
 
-	fn place_fnodes(&mut self) {
+	fn place_fnodes(&mut self, view: &mut ViewState) {
 		let spacing = self.ui.spacing().item_spacing.y;
 		let mut h = self.ui.next_widget_position().y;
 		for lofnode in self.lofnodes.iter_mut() {
+			let row_rect = Rect::from_min_size(
+				Pos2::new(self.ui.max_rect().left(), h),
+				Vec2::new(self.ui.max_rect().width(), lofnode.height(spacing)),
+			);
+			if self.ui.rect_contains_pointer(row_rect) {
+				view.hovered = Some((lofnode.fnode.pid, lofnode.fnode.node.id));
+			}
+
 			lofnode.checkbox.reposition(h + (lofnode.height(spacing) - spacing) / 2.0);
 			let checkbox_response = lofnode.checkbox.interact(self.ui);
 			self.ui.paint(&lofnode.checkbox, &checkbox_response);
 
-			if checkbox_response.clicked() {
+			if checkbox_response.clicked() && self.ui.input(|i| i.modifiers.shift) {
+				self.action = Action::SelectRange(lofnode.fnode.pid, lofnode.fnode.node.id);
+			} else if checkbox_response.clicked() {
 				self.action = Action::Toggle(lofnode.fnode.pid, lofnode.fnode.node.id);
+			} else if checkbox_response.secondary_clicked() {
+				self.action = Action::SelectSubtree(lofnode.fnode.pid, lofnode.fnode.node.id);
 			}
 
 			lofnode.text.reposition(h);
 			let label_response = lofnode.text.interact(self.ui);
-			self.ui.paint(&lofnode.text, &label_response);
 
-			if lofnode.fnode.pid != lofnode.fnode.node.id && label_response.clicked() {
-				self.action = Action::MoveInto(lofnode.fnode.pid, lofnode.fnode.node.id);
+			if view.editing == Some((lofnode.fnode.pid, lofnode.fnode.node.id)) {
+				let rect = label_response.rect.union(Rect::from_min_size(
+					label_response.rect.min,
+					Vec2::new(EDIT_WIDTH, label_response.rect.height()),
+				));
+				let edit_response = self.ui.put(rect, TextEdit::singleline(&mut view.edit_text));
+				view.edit_id = Some(edit_response.id);
+				if view.focus_edit {
+					edit_response.request_focus();
+					view.focus_edit = false;
+				}
+				if self.ui.input(|i| i.key_pressed(Key::Escape)) {
+					self.action = Action::CancelRename;
+				} else if edit_response.lost_focus() && self.ui.input(|i| i.key_pressed(Key::Enter)) {
+					self.action = Action::CommitRename;
+				}
+			} else {
+				self.ui.paint(&lofnode.text, &label_response);
+
+				if label_response.double_clicked() || label_response.secondary_clicked() {
+					self.action = Action::EditName(lofnode.fnode.pid, lofnode.fnode.node.id);
+					view.pending_open = None;
+				} else if lofnode.fnode.pid != lofnode.fnode.node.id && label_response.clicked() {
+					view.pending_open = Some((lofnode.fnode.pid, lofnode.fnode.node.id, self.ui.input(|i| i.time)));
+				}
+			}
+
+			if let Some(progress_label) = &mut lofnode.progress_label {
+				progress_label.reposition(h + (lofnode.height(spacing) - spacing) / 2.0);
+				let progress_response = progress_label.interact(self.ui);
+				self.ui.paint(progress_label, &progress_response);
+			}
+
+			if let Some(hidden_button) = &mut lofnode.hidden_button {
+				hidden_button.reposition(h + (lofnode.height(spacing) - spacing) / 2.0);
+				let hidden_response = hidden_button.interact(self.ui);
+				self.ui.paint(hidden_button, &hidden_response);
+
+				if hidden_response.clicked() && lofnode.fnode.depth > 0 {
+					self.action = Action::MoveInto(lofnode.fnode.pid, lofnode.fnode.node.id);
+				}
+			}
+
+			lofnode.done_button.reposition(h + (lofnode.height(spacing) - spacing) / 2.0);
+			let done_response = lofnode.done_button.interact(self.ui);
+			self.ui.paint(&lofnode.done_button, &done_response);
+
+			if done_response.clicked() {
+				self.action = Action::ToggleDone(lofnode.fnode.node.id);
 			}
 
 			lofnode.add_button.reposition(h + (lofnode.height(spacing) - spacing) / 2.0);
@@ -207,7 +410,13 @@ impl<'ui, 'lofnodes, 'node> TreeViewPainter<'ui, 'lofnodes, 'node> {
 				self.ui.paint(due_date_label, &label_response);
 			}
 
-			if lofnode.session_label.is_some() || lofnode.due_date_label.is_some() {
+			if let Some(done_label) = &mut lofnode.done_label {
+				done_label.reposition(h);
+				let label_response = done_label.interact(self.ui);
+				self.ui.paint(done_label, &label_response);
+			}
+
+			if lofnode.has_details() {
 				h += lofnode.height2 + self.ui.spacing().item_spacing.y;
 			}
 		}
@@ -277,6 +486,10 @@ fn color_from_prio(prio: &Priority) -> Color32 {
 	color_from_hsv((prio.det * 120) as f64 / prio.total as f64, 1.0, 1.0)
 }
 
+fn color_from_progress(progress: &Progress) -> Color32 {
+	color_from_hsv((progress.done * 120) as f64 / progress.total.max(1) as f64, 0.6, 1.0)
+}
+
 fn color_from_hsv(hue: f64, saturation: f64, value: f64) -> Color32 {
 	let c = value * saturation;
 	let h = hue / 60.0;
@@ -308,18 +521,26 @@ struct LaidOutFNode<'node> {
 	fnode: FNode<'node>,
 	checkbox: LaidOutCheckbox,
 	text: LaidOutLabel,
+	progress_label: Option<LaidOutLabel>,
+	hidden_button: Option<LaidOutButton>,
+	done_button: LaidOutButton,
 	add_button: LaidOutButton,
 	del_button: LaidOutButton,
 	session_label: Option<LaidOutLabel>,
 	due_date_label: Option<LaidOutLabel>,
+	done_label: Option<LaidOutLabel>,
 	height1: f32,
 	height2: f32,
 }
 
 impl LaidOutFNode<'_> {
+	fn has_details(&self) -> bool {
+		self.session_label.is_some() || self.due_date_label.is_some() || self.done_label.is_some()
+	}
+
 	fn height(&self, spacing: f32) -> f32 {
 		let mut h = self.height1 + spacing;
-		if self.session_label.is_some() || self.due_date_label.is_some() {
+		if self.has_details() {
 			h += self.height2 + spacing;
 		}
 		h
@@ -332,7 +553,15 @@ struct FNode<'node> {
 	pid: u64,
 	depth: usize,
 	selected: bool,
+	progress: Option<Progress>,
 	priority: Priority,
+	hidden: Hidden,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Hidden {
+	direct: usize,
+	total: usize,
 }
 
 struct Priority {
@@ -351,16 +580,18 @@ struct FChildIter<'node> {
 }
 
 impl<'node> FChildIter<'node> {
-	fn new(fnode: &FNode, tree: &'node Tree) -> Self {
+	fn new(fnode: &FNode, tree: &'node Tree, focus: Option<&HashSet<u64>>) -> Self {
 		let mut children = Vec::new();
-		for node in tree.children(fnode.node.id) {
+		for node in tree.visible_children(fnode.node.id, focus) {
 			children.push(FNode {
 				node,
 				path: fnode.path.clone(),
 				pid: fnode.node.id,
 				depth: fnode.path.len(),
 				selected: tree.is_selected(fnode.node.id, node.id),
+				progress: tree.progress(node.id),
 				priority: Priority { det: 0, total: 0 },
+				hidden: Hidden::default(),
 			});
 		}
 		for i in 0..children.len() {
@@ -373,3 +604,35 @@ impl<'node> FChildIter<'node> {
 		FChildIter { iter: children.into_iter() }
 	}
 }
+
+fn hidden_descendants(lofnodes: &[LaidOutFNode], tree: &Tree, focus: Option<&HashSet<u64>>) -> Vec<Hidden> {
+	let mut shown_children: HashMap<&[usize], usize> = HashMap::new();
+	let mut shown_descendants: HashMap<&[usize], usize> = HashMap::new();
+	for lofnode in lofnodes {
+		let path = &lofnode.fnode.path[..];
+		if let Some((_, parent)) = path.split_last() {
+			*shown_children.entry(parent).or_default() += 1;
+		}
+		for len in 1..path.len() {
+			*shown_descendants.entry(&path[..len]).or_default() += 1;
+		}
+	}
+	let mut totals = HashMap::new();
+	lofnodes.iter().map(|lofnode| {
+		let path = &lofnode.fnode.path[..];
+		let id = lofnode.fnode.node.id;
+		Hidden {
+			direct: tree.visible_children(id, focus).len().saturating_sub(shown_children.get(path).copied().unwrap_or(0)),
+			total: descendant_count(tree, id, focus, &mut totals).saturating_sub(shown_descendants.get(path).copied().unwrap_or(0)),
+		}
+	}).collect()
+}
+
+fn descendant_count(tree: &Tree, id: u64, focus: Option<&HashSet<u64>>, totals: &mut HashMap<u64, usize>) -> usize {
+	if let Some(&total) = totals.get(&id) { return total }
+	let total = tree.visible_children(id, focus).into_iter()
+		.map(|child| 1 + descendant_count(tree, child.id, focus, totals))
+		.sum();
+	totals.insert(id, total);
+	total
+}
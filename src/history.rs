@@ -0,0 +1,161 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use chrono::NaiveDateTime;
+use grus_lib::{Error, StoreWriter};
+use grus_lib::types::Session;
+use crate::node::{Node, Tree};
+use crate::snapshot::{resolve, Snapshot};
+
+const MAX_HISTORY: usize = 100;
+
+pub enum Change {
+	Add { pid: u64, name: String },
+	Remove { pid: u64, id: u64 },
+	Insert { pid: u64, snapshot: Snapshot },
+	Copy { pid: u64, snapshot: Snapshot },
+	Rename { id: u64, name: String },
+	SetDueDate { id: u64, due_date: Option<NaiveDateTime> },
+	AddSession { id: u64, session: Session },
+	RemoveSession { id: u64, session: Session },
+	Move { src_pid: u64, id: u64, dest_pid: u64 },
+	Link { id: u64, pid: u64 },
+}
+
+impl Change {
+	pub fn apply(self, writer: &mut StoreWriter, tree: &mut Tree, remap: &mut HashMap<u64, u64>) -> Result<Change, Error> {
+		Ok(match self {
+			Change::Add { pid, name } => {
+				let pid = resolve(remap, pid);
+				let id = writer.add_child(pid, &name)?;
+				tree.add_node(pid, Node { id, name, ..Node::default() });
+				Change::Remove { pid, id }
+			}
+			Change::Remove { pid, id } => {
+				let (pid, id) = (resolve(remap, pid), resolve(remap, id));
+				let snapshot = Snapshot::capture(tree, id, &tree.removed_by_delete(pid, id));
+				writer.delete(pid, id)?;
+				tree.delete(pid, id);
+				Change::Insert { pid, snapshot }
+			}
+			Change::Insert { pid, snapshot } => {
+				let pid = resolve(remap, pid);
+				match snapshot.restore(writer, tree, pid, remap, true)? {
+					Some(id) => Change::Remove { pid, id },
+					None => Change::Insert { pid, snapshot },
+				}
+			}
+			Change::Copy { pid, snapshot } => {
+				let pid = resolve(remap, pid);
+				match snapshot.restore(writer, tree, pid, &mut HashMap::new(), false)? {
+					Some(id) => Change::Remove { pid, id },
+					None => Change::Copy { pid, snapshot },
+				}
+			}
+			Change::Rename { id, name } => {
+				let id = resolve(remap, id);
+				writer.rename(id, &name)?;
+				let prev = mem::replace(&mut tree.node_mut(id).name, name);
+				Change::Rename { id, name: prev }
+			}
+			Change::SetDueDate { id, due_date } => {
+				let id = resolve(remap, id);
+				match due_date {
+					Some(due_date) => writer.set_due_date(id, due_date)?,
+					None => writer.unset_due_date(id)?,
+				}
+				let prev = mem::replace(&mut tree.node_mut(id).due_date, due_date);
+				Change::SetDueDate { id, due_date: prev }
+			}
+			Change::AddSession { id, session } => {
+				let id = resolve(remap, id);
+				writer.add_session(id, &session)?;
+				tree.node_mut(id).sessions.push(session);
+				Change::RemoveSession { id, session }
+			}
+			Change::RemoveSession { id, session } => {
+				let id = resolve(remap, id);
+				writer.delete_session(id, &session)?;
+				tree.node_mut(id).sessions.retain(|s| s.start != session.start || s.end != session.end);
+				Change::AddSession { id, session }
+			}
+			Change::Move { src_pid, id, dest_pid } => {
+				let (src_pid, id, dest_pid) = (resolve(remap, src_pid), resolve(remap, id), resolve(remap, dest_pid));
+				writer.cut(src_pid, id, dest_pid)?;
+				tree.unlink(src_pid, id);
+				tree.link(dest_pid, id);
+				Change::Move { src_pid: dest_pid, id, dest_pid: src_pid }
+			}
+			Change::Link { id, pid } => {
+				let (id, pid) = (resolve(remap, id), resolve(remap, pid));
+				writer.share(id, pid)?;
+				tree.link(pid, id);
+				Change::Remove { pid, id }
+			}
+		})
+	}
+}
+
+#[derive(Default)]
+pub struct History {
+	undo: VecDeque<Vec<Change>>,
+	redo: Vec<Vec<Change>>,
+	pub remap: HashMap<u64, u64>,
+}
+
+impl History {
+	pub fn record(&mut self, inverse: Vec<Change>) {
+		self.redo.clear();
+		self.push_undo(inverse);
+	}
+
+	pub fn push_undo(&mut self, inverse: Vec<Change>) {
+		if inverse.is_empty() { return }
+		if self.undo.len() == MAX_HISTORY {
+			self.undo.pop_front();
+		}
+		self.undo.push_back(inverse);
+	}
+
+	pub fn push_redo(&mut self, inverse: Vec<Change>) {
+		if inverse.is_empty() { return }
+		self.redo.push(inverse);
+	}
+
+	pub fn pop_undo(&mut self) -> Option<Vec<Change>> {
+		self.undo.pop_back()
+	}
+
+	pub fn pop_redo(&mut self) -> Option<Vec<Change>> {
+		self.redo.pop()
+	}
+
+	pub fn can_undo(&self) -> bool {
+		!self.undo.is_empty()
+	}
+
+	pub fn can_redo(&self) -> bool {
+		!self.redo.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.undo.clear();
+		self.redo.clear();
+		self.remap.clear();
+	}
+}
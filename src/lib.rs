@@ -17,8 +17,20 @@
 #[cfg(target_os = "android")]
 mod android;
 mod app;
+mod completion;
+mod history;
+mod next;
 mod node;
+mod ordering;
+mod outline;
+mod rename;
 mod ftree;
+mod selection_set;
+mod series;
+mod sidecar;
+mod snapshot;
+mod template;
+mod trash;
 mod vboard;
 
 use eframe::NativeOptions;
@@ -31,12 +43,14 @@ use winit::platform::android::activity::AndroidApp;
 use android::JniWrapper;
 use app::Grus;
 
+const ROOTS: &[&str] = &["Tasks", "Archive"];
+
 pub fn _main<P: AsRef<Path>>(
 	options: NativeOptions,
 	data_path: P,
 	#[cfg(target_os = "android")] jniwr: JniWrapper
 ) {
-	let app = match Grus::new(data_path, 2, #[cfg(target_os = "android")] jniwr) {
+	let app = match Grus::new(data_path, ROOTS, #[cfg(target_os = "android")] jniwr) {
 		Ok(app) => app,
 		Err(err) => { eprintln!("Failed to open store: {}", err); return; }
 	};
@@ -0,0 +1,205 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDateTime;
+use egui::{Color32, DragValue, RichText, ScrollArea, Ui};
+use crate::app::Action;
+use crate::node::{Displayable, Node, Tree};
+use crate::sidecar::{self, invalid_data};
+
+// Due dates further away than this don't add to the urgency of a task.
+const DUE_HORIZON_DAYS: f64 = 14.0;
+
+pub struct Urgency {
+	path: PathBuf,
+	pub due: f64,
+	pub priority: f64,
+	pub depth: f64,
+}
+
+impl Urgency {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "urgency");
+		let mut urgency = Urgency { path, due: 1.0, priority: 0.5, depth: 0.25 };
+		for record in sidecar::read_records(&urgency.path)? {
+			let [name, weight] = &record[..] else { return Err(invalid_data(&urgency.path)) };
+			let Ok(weight) = weight.parse() else { return Err(invalid_data(&urgency.path)) };
+			match name.as_str() {
+				"due" => urgency.due = weight,
+				"priority" => urgency.priority = weight,
+				"depth" => urgency.depth = weight,
+				_ => return Err(invalid_data(&urgency.path)),
+			}
+		}
+		Ok(urgency)
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		sidecar::write_records(&self.path, [
+			["due".to_string(), self.due.to_string()],
+			["priority".to_string(), self.priority.to_string()],
+			["depth".to_string(), self.depth.to_string()],
+		])
+	}
+
+	// Overdue tasks keep gaining urgency until they are as far past their due date as the horizon.
+	fn score(&self, node: &Node, det: usize, total: usize, depth: usize, now: NaiveDateTime) -> f64 {
+		let due = node.due_date.map_or(0.0, |due_date| {
+			let days = (due_date - now).num_minutes() as f64 / (24.0 * 60.0);
+			((DUE_HORIZON_DAYS - days) / DUE_HORIZON_DAYS).clamp(0.0, 2.0)
+		});
+		let priority = 1.0 - det as f64 / total as f64;
+		let depth = 1.0 - 1.0 / (depth as f64 + 1.0);
+		self.due * due + self.priority * priority + self.depth * depth
+	}
+}
+
+pub struct NextAction {
+	id: u64,
+	ancestors: Vec<(u64, u64)>,
+	score: f64,
+}
+
+// Walking every path is exponential once tasks are shared, so the list is kept until the tree or the weights change.
+#[derive(Default)]
+pub struct NextActions {
+	key: Option<(u64, u64, u64, [f64; 3])>,
+	actions: Vec<NextAction>,
+}
+
+impl NextActions {
+	pub fn get(&mut self, tree: &Tree, pid: u64, id: u64, urgency: &Urgency, now: NaiveDateTime) -> &[NextAction] {
+		let key = (tree.generation(), pid, id, [urgency.due, urgency.priority, urgency.depth]);
+		if self.key != Some(key) {
+			self.actions = next_actions(tree, pid, id, urgency, now);
+			self.key = Some(key);
+		}
+		&self.actions
+	}
+}
+
+// Parents come before their children, so each task's deepest path is known before its children are scored.
+fn next_actions(tree: &Tree, pid: u64, id: u64, urgency: &Urgency, now: NaiveDateTime) -> Vec<NextAction> {
+	let mut depths = HashMap::from([(id, 0)]);
+	let mut via = HashMap::new();
+	let mut leaves: HashMap<u64, (u64, f64)> = HashMap::new();
+	for parent_id in topological_order(tree, id) {
+		let depth = depths[&parent_id] + 1;
+		let children = tree.ordered_children(parent_id);
+		let total = children.len();
+		for (det, child) in children.into_iter().enumerate() {
+			if !depths.get(&child.id).is_some_and(|&d| d >= depth) {
+				depths.insert(child.id, depth);
+				via.insert(child.id, parent_id);
+			}
+			if child.done.is_some() || tree.children(child.id).next().is_some() { continue }
+			let score = urgency.score(child, det, total, depth, now);
+			if !leaves.get(&child.id).is_some_and(|&(_, best)| best >= score) {
+				leaves.insert(child.id, (parent_id, score));
+			}
+		}
+	}
+	let mut actions: Vec<NextAction> = leaves.into_iter().map(|(leaf_id, (parent_id, score))| {
+		let mut ancestors = vec![(parent_id, leaf_id)];
+		let mut row_id = parent_id;
+		while row_id != id {
+			let parent_id = via[&row_id];
+			ancestors.push((parent_id, row_id));
+			row_id = parent_id;
+		}
+		ancestors.push((pid, id));
+		ancestors.reverse();
+		NextAction { id: leaf_id, ancestors, score }
+	}).collect();
+	actions.sort_by(|l, r| {
+		r.score.total_cmp(&l.score).then_with(|| tree.node_at(l.id).name.cmp(&tree.node_at(r.id).name))
+	});
+	actions
+}
+
+fn topological_order(tree: &Tree, id: u64) -> Vec<u64> {
+	let mut order = Vec::new();
+	let mut seen = HashSet::from([id]);
+	let mut stack = vec![(id, tree.children(id))];
+	while let Some((pid, children)) = stack.last_mut() {
+		match children.next() {
+			Some(child) => if seen.insert(child.id) {
+				stack.push((child.id, tree.children(child.id)));
+			}
+			None => {
+				order.push(*pid);
+				stack.pop();
+			}
+		}
+	}
+	order.reverse();
+	order
+}
+
+pub trait NextActionsView {
+	fn next_actions_view(&mut self, tree: &Tree, actions: &[NextAction], urgency: &mut Urgency) -> Action;
+}
+
+impl NextActionsView for Ui {
+	fn next_actions_view(&mut self, tree: &Tree, actions: &[NextAction], urgency: &mut Urgency) -> Action {
+		let mut action = Action::None;
+		self.horizontal(|ui| {
+			ui.label("Weights");
+			for (weight, prefix) in [
+				(&mut urgency.due, "due "),
+				(&mut urgency.priority, "priority "),
+				(&mut urgency.depth, "depth "),
+			] {
+				let response = ui.add(DragValue::new(weight).speed(0.05).clamp_range(0.0..=10.0).prefix(prefix));
+				if response.drag_released() || (response.changed() && !response.dragged()) {
+					action = Action::SaveUrgency;
+				}
+			}
+		});
+
+		ScrollArea::vertical().show(self, |ui| {
+			for next in actions {
+				let node = tree.node_at(next.id);
+				ui.horizontal(|ui| {
+					if ui.button("󰝦").clicked() {
+						action = Action::ToggleDone(node.id);
+					}
+					ui.vertical(|ui| {
+						let &(pid, id) = &next.ancestors[next.ancestors.len() - 2];
+						let mut name = RichText::new(&node.name);
+						if let Some(due_date) = node.due_date {
+							name = RichText::new(format!("{}  󰃰 {}", node.name, Displayable(Some(due_date))));
+						}
+						if ui.link(name).clicked() {
+							action = Action::MoveInto(pid, id);
+						}
+						let path: Vec<&str> = next.ancestors[1..next.ancestors.len() - 1].iter()
+							.map(|&(_, id)| tree.node_at(id).name.as_str())
+							.collect();
+						if !path.is_empty() {
+							ui.label(RichText::new(path.join(" › ")).small().color(Color32::GRAY));
+						}
+					});
+				});
+				ui.separator();
+			}
+		});
+		action
+	}
+}
@@ -17,9 +17,12 @@
 use std::iter;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display, Formatter};
+use std::io;
 use chrono::{Datelike, Local, NaiveDateTime};
 use grus_lib::{Error, Store};
 use grus_lib::types::Session;
+use crate::completion::Completions;
+use crate::ordering::{ChildOrders, SortMode};
 
 #[derive(Default)]
 pub struct Node {
@@ -27,19 +30,42 @@ pub struct Node {
 	pub name: String,
 	pub due_date: Option<NaiveDateTime>,
 	pub session: Option<Session>,
+	pub sessions: Vec<Session>,
+	pub done: Option<NaiveDateTime>,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Progress {
+	pub done: u64,
+	pub total: u64,
+}
+
+#[derive(Default)]
+pub struct DeleteImpact {
+	pub removed: usize,
+	pub shared: usize,
+	pub sessions: usize,
+	pub due_dates: usize,
 }
 
 #[derive(Default)]
 pub struct Tree {
 	nodes: HashMap<u64, Node>,
 	links: HashMap<u64, Vec<u64>>,
+	parents: HashMap<u64, Vec<u64>>,
+	progress: HashMap<u64, Progress>,
 	selections: HashMap<u64, HashSet<u64>>,
+	completions: Completions,
+	orders: ChildOrders,
+	generation: u64,
 	pub highlighted: Option<u64>,
+	pub hide_done: bool,
+	pub focus: bool,
 }
 
 impl Tree {
-	pub fn from_store(store: &Store) -> Result<Tree, Error> {
-		let mut tree = Tree::default();
+	pub fn from_store(store: &Store, completions: Completions, orders: ChildOrders) -> Result<Tree, Error> {
+		let mut tree = Tree { completions, orders, ..Tree::default() };
 		tree.rebuild(store)?;
 		Ok(tree)
 	}
@@ -47,6 +73,9 @@ impl Tree {
 	pub fn rebuild(&mut self, store: &Store) -> Result<(), Error> {
 		self.nodes.clear();
 		self.links.clear();
+		self.parents.clear();
+		self.progress.clear();
+		self.generation += 1;
 
 		let reader = store.reader()?;
 		for entry in reader.all_names()? {
@@ -56,12 +85,104 @@ impl Tree {
 				name: name.to_string().into(),
 				due_date: reader.due_date(id)?,
 				session: reader.first_session(id)?,
+				sessions: reader.sessions(id)?.collect::<Result<_, Error>>()?,
+				done: self.completions.get(id),
 			});
 			self.links.insert(id, reader.child_ids(id)?.collect::<Result<_, Error>>()?);
 		}
+		for (&pid, ids) in &self.links {
+			for &id in ids {
+				self.parents.entry(id).or_default().push(pid);
+			}
+		}
+		self.selections.retain(|id, pids| {
+			pids.retain(|pid| *pid == *id || self.links.get(pid).is_some_and(|ids| ids.contains(id)));
+			self.nodes.contains_key(id) && !pids.is_empty()
+		});
+		let ids: Vec<u64> = self.nodes.keys().copied().collect();
+		for id in ids {
+			self.compute_progress(id);
+		}
 		Ok(())
 	}
 
+	// State kept for deleted nodes outlives them while a restore can still bring them back.
+	pub fn retain_sidecars(&mut self, keep: impl Fn(u64) -> bool) -> io::Result<()> {
+		self.completions.retain(|id| self.nodes.contains_key(&id) || keep(id));
+		self.orders.retain(|id| self.nodes.contains_key(&id) || keep(id));
+		self.completions.save()?;
+		self.orders.save()
+	}
+
+	pub fn clear_sidecars(&mut self) -> io::Result<()> {
+		self.completions.clear();
+		self.orders.clear();
+		self.selections.clear();
+		self.completions.save()?;
+		self.orders.save()
+	}
+
+	pub fn rekey(&mut self, ids: &HashMap<u64, u64>) -> io::Result<()> {
+		self.completions.rekey(ids);
+		self.orders.rekey(ids);
+		self.completions.save()?;
+		self.orders.save()
+	}
+
+	fn compute_progress(&mut self, id: u64) -> Progress {
+		if let Some(&progress) = self.progress.get(&id) { return progress }
+		let progress = if self.links[&id].is_empty() {
+			Progress { done: self.nodes[&id].done.is_some() as u64, total: 1 }
+		} else {
+			let mut progress = Progress::default();
+			for child_id in self.links[&id].clone() {
+				let child = self.compute_progress(child_id);
+				progress.done += child.done;
+				progress.total += child.total;
+			}
+			progress
+		};
+		self.progress.insert(id, progress);
+		progress
+	}
+
+	// Bumped by every change that views caching a walk of the tree need to notice.
+	pub fn generation(&self) -> u64 {
+		self.generation
+	}
+
+	pub fn set_done(&mut self, ids: impl IntoIterator<Item = u64>, at: Option<NaiveDateTime>) -> io::Result<()> {
+		self.generation += 1;
+		for id in ids {
+			self.completions.set(id, at);
+			if let Some(node) = self.nodes.get_mut(&id) {
+				let was_done = node.done.is_some();
+				node.done = at;
+				if was_done != at.is_some() && self.links[&id].is_empty() {
+					self.propagate_progress(id, at.is_some());
+				}
+			}
+		}
+		self.completions.save()
+	}
+
+	fn propagate_progress(&mut self, id: u64, done: bool) {
+		let mut stack = vec![id];
+		while let Some(id) = stack.pop() {
+			if let Some(progress) = self.progress.get_mut(&id) {
+				if done { progress.done += 1 } else { progress.done -= 1 }
+			}
+			if let Some(pids) = self.parents.get(&id) {
+				stack.extend(pids);
+			}
+		}
+	}
+
+	pub fn progress(&self, id: u64) -> Option<Progress> {
+		if self.links[&id].is_empty() { return None }
+		self.progress.get(&id).copied()
+	}
+
 	pub fn toggle(&mut self, pid: u64, id: u64) {
 		if let Some(pids) = self.selections.get_mut(&id) {
 			if !pids.insert(pid) {
@@ -75,6 +196,173 @@ impl Tree {
 		}
 	}
 
+	pub fn select(&mut self, pid: u64, id: u64, selected: bool) {
+		if selected != self.is_selected(pid, id) {
+			self.toggle(pid, id);
+		}
+	}
+
+	pub fn select_subtree(&mut self, pid: u64, id: u64) {
+		self.select(pid, id, true);
+		let mut stack = vec![id];
+		let mut seen = HashSet::from([id]);
+		while let Some(pid) = stack.pop() {
+			for id in self.links[&pid].clone() {
+				self.select(pid, id, true);
+				if seen.insert(id) {
+					stack.push(id);
+				}
+			}
+		}
+	}
+
+	pub fn selection_count(&self) -> usize {
+		self.selections.values().map(HashSet::len).sum()
+	}
+
+	pub fn clear_selections(&mut self) {
+		self.selections.clear();
+	}
+
+	pub fn contains(&self, id: u64) -> bool {
+		self.nodes.contains_key(&id)
+	}
+
+	// The methods below mirror store writes so that later changes in the same transaction see them.
+	// Progress is left stale until the rebuild after commit.
+	pub fn add_node(&mut self, pid: u64, node: Node) {
+		self.links.insert(node.id, Vec::new());
+		self.link(pid, node.id);
+		self.nodes.insert(node.id, node);
+	}
+
+	pub fn link(&mut self, pid: u64, id: u64) {
+		self.links.entry(pid).or_default().push(id);
+		self.parents.entry(id).or_default().push(pid);
+	}
+
+	pub fn unlink(&mut self, pid: u64, id: u64) {
+		if let Some(ids) = self.links.get_mut(&pid) {
+			ids.retain(|&child_id| child_id != id);
+		}
+		if let Some(pids) = self.parents.get_mut(&id) {
+			pids.retain(|&p| p != pid);
+		}
+	}
+
+	pub fn delete(&mut self, pid: u64, id: u64) {
+		let removed = self.removed_by_delete(pid, id);
+		self.unlink(pid, id);
+		for id in removed {
+			for child_id in self.links.remove(&id).unwrap_or_default() {
+				self.unlink(id, child_id);
+			}
+			self.nodes.remove(&id);
+			self.parents.remove(&id);
+		}
+	}
+
+	pub fn node_mut(&mut self, id: u64) -> &mut Node {
+		self.nodes.get_mut(&id).unwrap()
+	}
+
+	pub fn removed_by_delete(&self, pid: u64, id: u64) -> HashSet<u64> {
+		let mut removed = HashSet::new();
+		if self.parents.get(&id).is_some_and(|pids| pids.iter().any(|&p| p != pid)) {
+			return removed;
+		}
+		removed.insert(id);
+		let mut descendants = Vec::new();
+		let mut stack = vec![id];
+		let mut seen = HashSet::from([id]);
+		while let Some(id) = stack.pop() {
+			for &child_id in &self.links[&id] {
+				if seen.insert(child_id) {
+					descendants.push(child_id);
+					stack.push(child_id);
+				}
+			}
+		}
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for &id in &descendants {
+				if !removed.contains(&id) && self.parents[&id].iter().all(|pid| removed.contains(pid)) {
+					removed.insert(id);
+					changed = true;
+				}
+			}
+		}
+		removed
+	}
+
+	pub fn descendants(&self, id: u64) -> HashSet<u64> {
+		let mut descendants = HashSet::from([id]);
+		let mut stack = vec![id];
+		while let Some(id) = stack.pop() {
+			for &child_id in &self.links[&id] {
+				if descendants.insert(child_id) {
+					stack.push(child_id);
+				}
+			}
+		}
+		descendants
+	}
+
+	pub fn descends_from(&self, id: u64, ancestor: u64) -> bool {
+		let mut stack = vec![ancestor];
+		let mut seen = HashSet::new();
+		while let Some(pid) = stack.pop() {
+			if pid == id { return true }
+			if seen.insert(pid) {
+				stack.extend(&self.links[&pid]);
+			}
+		}
+		false
+	}
+
+	pub fn previous_sibling(&self, pid: u64, id: u64, skip: impl Fn(u64) -> bool) -> Option<u64> {
+		if !self.contains(pid) { return None }
+		let siblings = self.visible_children(pid, self.focus_ancestors().as_ref());
+		let i = siblings.iter().position(|sibling| sibling.id == id)?;
+		siblings[..i].iter().rev().map(|sibling| sibling.id).find(|&sibling| !skip(sibling))
+	}
+
+	pub fn parent_ids(&self, id: u64) -> &[u64] {
+		self.parents.get(&id).map_or(&[], Vec::as_slice)
+	}
+
+	pub fn is_child(&self, pid: u64, id: u64) -> bool {
+		self.links[&pid].contains(&id)
+	}
+
+	pub fn delete_impact(&self, pid: u64, id: u64) -> DeleteImpact {
+		let removed = self.removed_by_delete(pid, id);
+		let mut shared = HashSet::new();
+		for &id in &removed {
+			for &child_id in &self.links[&id] {
+				if !removed.contains(&child_id) {
+					shared.insert(child_id);
+				}
+			}
+		}
+		DeleteImpact {
+			removed: removed.len(),
+			shared: shared.len(),
+			sessions: removed.iter().map(|id| self.nodes[id].sessions.len()).sum(),
+			due_dates: removed.iter().filter(|&id| self.nodes[id].due_date.is_some()).count(),
+		}
+	}
+
+	pub fn bulk_delete_impact(&self, targets: &[(u64, u64)]) -> DeleteImpact {
+		targets.iter().map(|&(pid, id)| self.delete_impact(pid, id)).fold(DeleteImpact::default(), |total, impact| DeleteImpact {
+			removed: total.removed + impact.removed,
+			shared: total.shared + impact.shared,
+			sessions: total.sessions + impact.sessions,
+			due_dates: total.due_dates + impact.due_dates,
+		})
+	}
+
 	pub fn node_at(&self, id: u64) -> &Node {
 		&self.nodes[&id]
 	}
@@ -83,6 +371,41 @@ impl Tree {
 		self.links[&id].iter().map(|id| &self.nodes[&id])
 	}
 
+	pub fn ordered_children(&self, id: u64) -> Vec<&Node> {
+		let mut children: Vec<&Node> = self.children(id).collect();
+		self.orders.sort(id, &mut children);
+		children
+	}
+
+	// Children in the order rows show them, leaving out whatever hide done or focus hides.
+	pub fn visible_children(&self, pid: u64, focus: Option<&HashSet<u64>>) -> Vec<&Node> {
+		let mut children = self.ordered_children(pid);
+		children.retain(|node| {
+			!(self.hide_done && node.done.is_some())
+				&& !focus.is_some_and(|ancestors| !ancestors.contains(&node.id) && !self.is_selected(pid, node.id))
+		});
+		children
+	}
+
+	pub fn sort_mode(&self, id: u64) -> SortMode {
+		self.orders.mode(id)
+	}
+
+	pub fn set_sort_mode(&mut self, id: u64, mode: SortMode) -> io::Result<()> {
+		self.orders.set_mode(id, mode);
+		self.generation += 1;
+		self.orders.save()
+	}
+
+	pub fn persist_order(&mut self, id: u64, mode: SortMode) -> io::Result<()> {
+		let mut children: Vec<&Node> = self.ordered_children(id);
+		mode.sort(&mut children);
+		let manual = children.into_iter().map(|node| node.id).collect();
+		self.orders.set_manual(id, manual);
+		self.generation += 1;
+		self.orders.save()
+	}
+
 	pub fn selections(&self) -> impl Iterator<Item = (&u64, &u64)> {
 		if !self.selections.is_empty() {
 			Selections::Actual(self.selections.iter().flat_map(|(id, pids)| pids.iter().zip(iter::repeat(id))))
@@ -99,6 +422,18 @@ impl Tree {
 		}
 	}
 
+	pub fn focus_ancestors(&self) -> Option<HashSet<u64>> {
+		if !self.focus || self.selections.is_empty() { return None }
+		let mut ancestors = HashSet::new();
+		let mut stack: Vec<u64> = self.selections().map(|(&pid, _)| pid).collect();
+		while let Some(id) = stack.pop() {
+			if ancestors.insert(id) {
+				stack.extend(self.parent_ids(id));
+			}
+		}
+		Some(ancestors)
+	}
+
 	pub fn is_selected(&self, pid: u64, id: u64) -> bool {
 		if let Some(pids) = self.selections.get(&id) {
 			pids.contains(&pid)
@@ -166,3 +501,19 @@ impl Display for Displayable<Session> {
 		write!(f, "{} to {}", Displayable(Some(session.start)), Displayable(Some(session.end)))
 	}
 }
+
+#[cfg(test)]
+impl Tree {
+	pub fn from_nodes(nodes: Vec<Node>, links: &[(u64, u64)]) -> Tree {
+		let mut tree = Tree::default();
+		for node in nodes {
+			tree.links.insert(node.id, Vec::new());
+			tree.nodes.insert(node.id, node);
+		}
+		for &(pid, id) in links {
+			tree.links.get_mut(&pid).unwrap().push(id);
+			tree.parents.entry(id).or_default().push(pid);
+		}
+		tree
+	}
+}
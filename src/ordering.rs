@@ -0,0 +1,164 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::Duration;
+use crate::node::Node;
+use crate::sidecar::{self, invalid_data, parse_id};
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum SortMode {
+	#[default]
+	Manual,
+	Name,
+	DueDate,
+	RecentSession,
+	TotalTime,
+}
+
+impl SortMode {
+	pub const ALL: [SortMode; 5] = [
+		SortMode::Manual,
+		SortMode::Name,
+		SortMode::DueDate,
+		SortMode::RecentSession,
+		SortMode::TotalTime,
+	];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			SortMode::Manual => "Manual",
+			SortMode::Name => "Name",
+			SortMode::DueDate => "Due date",
+			SortMode::RecentSession => "Most recent session",
+			SortMode::TotalTime => "Total time",
+		}
+	}
+
+	fn key(self) -> &'static str {
+		match self {
+			SortMode::Manual => "manual",
+			SortMode::Name => "name",
+			SortMode::DueDate => "due",
+			SortMode::RecentSession => "recent",
+			SortMode::TotalTime => "time",
+		}
+	}
+
+	fn from_key(key: &str) -> Option<Self> {
+		SortMode::ALL.into_iter().find(|mode| mode.key() == key)
+	}
+
+	pub fn sort(self, nodes: &mut [&Node]) {
+		match self {
+			SortMode::Manual => {}
+			SortMode::Name => nodes.sort_by_cached_key(|node| node.name.to_lowercase()),
+			SortMode::DueDate => nodes.sort_by_key(|node| (node.due_date.is_none(), node.due_date)),
+			SortMode::RecentSession => nodes.sort_by_key(|node| {
+				Reverse(node.sessions.iter().map(|session| session.end).max())
+			}),
+			SortMode::TotalTime => nodes.sort_by_cached_key(|node| {
+				Reverse(node.sessions.iter().map(|session| session.end - session.start).fold(Duration::zero(), |a, b| a + b))
+			}),
+		}
+	}
+}
+
+#[derive(Default)]
+struct ChildOrder {
+	mode: SortMode,
+	manual: Vec<u64>,
+}
+
+#[derive(Default)]
+pub struct ChildOrders {
+	path: PathBuf,
+	orders: HashMap<u64, ChildOrder>,
+}
+
+impl ChildOrders {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "order");
+		let mut orders = HashMap::new();
+		for record in sidecar::read_records(&path)? {
+			let [pid, mode, manual] = &record[..] else { return Err(invalid_data(&path)) };
+			let Some(mode) = SortMode::from_key(mode) else { return Err(invalid_data(&path)) };
+			let manual = manual.split(',')
+				.filter(|id| !id.is_empty())
+				.map(|id| parse_id(&path, id))
+				.collect::<io::Result<_>>()?;
+			orders.insert(parse_id(&path, pid)?, ChildOrder { mode, manual });
+		}
+		Ok(ChildOrders { path, orders })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		sidecar::write_records(&self.path, self.orders.iter().map(|(pid, order)| [
+			pid.to_string(),
+			order.mode.key().to_string(),
+			order.manual.iter().map(u64::to_string).collect::<Vec<_>>().join(","),
+		]))
+	}
+
+	pub fn mode(&self, pid: u64) -> SortMode {
+		self.orders.get(&pid).map_or(SortMode::Manual, |order| order.mode)
+	}
+
+	pub fn set_mode(&mut self, pid: u64, mode: SortMode) {
+		self.orders.entry(pid).or_default().mode = mode;
+	}
+
+	pub fn set_manual(&mut self, pid: u64, manual: Vec<u64>) {
+		self.orders.insert(pid, ChildOrder { mode: SortMode::Manual, manual });
+	}
+
+	// Children missing from the remembered order are newer than it and keep their store order at the end.
+	pub fn sort(&self, pid: u64, nodes: &mut [&Node]) {
+		let Some(order) = self.orders.get(&pid) else { return };
+		if !order.manual.is_empty() {
+			let rank: HashMap<u64, usize> = order.manual.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+			nodes.sort_by_key(|node| rank.get(&node.id).copied().unwrap_or(usize::MAX));
+		}
+		order.mode.sort(nodes);
+	}
+
+	pub fn retain(&mut self, mut f: impl FnMut(u64) -> bool) {
+		self.orders.retain(|&pid, order| {
+			order.manual.retain(|&id| f(id));
+			f(pid)
+		});
+	}
+
+	pub fn clear(&mut self) {
+		self.orders.clear();
+	}
+
+	pub fn rekey(&mut self, ids: &HashMap<u64, u64>) {
+		for (old_pid, &pid) in ids {
+			if let Some(order) = self.orders.remove(old_pid) {
+				self.orders.insert(pid, order);
+			}
+		}
+		for order in self.orders.values_mut() {
+			for id in &mut order.manual {
+				*id = ids.get(id).copied().unwrap_or(*id);
+			}
+		}
+	}
+}
@@ -0,0 +1,294 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use chrono::NaiveDateTime;
+use grus_lib::types::Session;
+use crate::node::Tree;
+use crate::snapshot::Snapshot;
+
+const TAB_WIDTH: usize = 4;
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+const REPEAT_MARKER: &str = "↪ ";
+
+pub fn parse(text: &str) -> Vec<Snapshot> {
+	let mut indents: Vec<usize> = Vec::new();
+	let mut refs = HashMap::new();
+	let mut stack = vec![Snapshot::Node {
+		id: 0,
+		name: String::new(),
+		due_date: None,
+		sessions: Vec::new(),
+		children: Vec::new(),
+	}];
+	for (i, line) in text.lines().enumerate() {
+		let item = strip_marker(line.trim());
+		if item.is_empty() { continue }
+		let indent = line.chars()
+			.take_while(|c| c.is_whitespace())
+			.map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+			.sum();
+		while indents.last().is_some_and(|&last| last >= indent) {
+			indents.pop();
+		}
+		let level = indents.len();
+		indents.push(indent);
+		while stack.len() > level + 1 {
+			collapse(&mut stack);
+		}
+
+		let (item, repeat) = match item.strip_prefix(REPEAT_MARKER) {
+			Some(item) => (item, true),
+			None => (item, false),
+		};
+		let (name, due_date, sessions, reference) = parse_annotations(item);
+		let id = i as u64 + 1;
+		match reference.and_then(|reference| refs.get(&reference)) {
+			Some(&id) if repeat => stack.push(Snapshot::Link(id)),
+			_ => {
+				if let Some(reference) = reference {
+					refs.insert(reference, id);
+				}
+				stack.push(Snapshot::Node { id, name: unescape(name), due_date, sessions, children: Vec::new() });
+			}
+		}
+	}
+	while stack.len() > 1 {
+		collapse(&mut stack);
+	}
+	match stack.pop() {
+		Some(Snapshot::Node { children, .. }) => children,
+		_ => Vec::new(),
+	}
+}
+
+pub fn format(tree: &Tree, id: u64) -> String {
+	let mut visits = HashMap::new();
+	count_visits(tree, id, &mut visits);
+	let mut refs = HashMap::new();
+	for (&id, _) in visits.iter().filter(|&(_, &count)| count > 1) {
+		refs.insert(id, refs.len() + 1);
+	}
+	let mut text = String::new();
+	format_node(tree, id, 0, &refs, &mut HashSet::new(), &mut text);
+	text
+}
+
+fn count_visits(tree: &Tree, id: u64, visits: &mut HashMap<u64, usize>) {
+	let count = visits.entry(id).or_insert(0);
+	*count += 1;
+	if *count > 1 { return }
+	for child in tree.children(id) {
+		count_visits(tree, child.id, visits);
+	}
+}
+
+fn format_node(
+	tree: &Tree,
+	id: u64,
+	depth: usize,
+	refs: &HashMap<u64, usize>,
+	emitted: &mut HashSet<u64>,
+	text: &mut String,
+) {
+	let node = tree.node_at(id);
+	let indent = "\t".repeat(depth);
+	if !emitted.insert(id) {
+		_ = writeln!(text, "{}- {}{} ^{}", indent, REPEAT_MARKER, escape(&node.name), refs[&id]);
+		return;
+	}
+	_ = write!(text, "{}- {}", indent, escape(&node.name));
+	if let Some(due_date) = node.due_date {
+		_ = write!(text, " @due({})", due_date.format(DATETIME_FORMAT));
+	}
+	for session in &node.sessions {
+		_ = write!(text, " @session({} / {})", session.start.format(DATETIME_FORMAT), session.end.format(DATETIME_FORMAT));
+	}
+	if let Some(reference) = refs.get(&id) {
+		_ = write!(text, " ^{}", reference);
+	}
+	text.push('\n');
+	for child in tree.children(id) {
+		format_node(tree, child.id, depth + 1, refs, emitted, text);
+	}
+}
+
+fn collapse(stack: &mut Vec<Snapshot>) {
+	let snapshot = stack.pop().unwrap();
+	if let Some(Snapshot::Node { children, .. }) = stack.last_mut() {
+		children.push(snapshot);
+	}
+}
+
+fn parse_annotations(item: &str) -> (&str, Option<NaiveDateTime>, Vec<Session>, Option<u64>) {
+	let mut rest = item.trim_end();
+	let mut due_date = None;
+	let mut sessions = Vec::new();
+	let mut reference = None;
+	loop {
+		if let Some((head, tail)) = rest.rsplit_once(" ^") {
+			if let Ok(r) = tail.parse() {
+				reference = Some(r);
+				rest = head.trim_end();
+				continue;
+			}
+		}
+		let Some((head, tail)) = rest.rsplit_once(" @") else { break };
+		if let Some(dt) = tail.strip_prefix("due(").and_then(|tail| tail.strip_suffix(')')) {
+			let Ok(dt) = NaiveDateTime::parse_from_str(dt, DATETIME_FORMAT) else { break };
+			due_date = Some(dt);
+		} else if let Some(session) = tail.strip_prefix("session(").and_then(|tail| tail.strip_suffix(')')) {
+			let Some((start, end)) = session.split_once(" / ") else { break };
+			let (Ok(start), Ok(end)) = (
+				NaiveDateTime::parse_from_str(start, DATETIME_FORMAT),
+				NaiveDateTime::parse_from_str(end, DATETIME_FORMAT),
+			) else { break };
+			sessions.insert(0, Session { start, end });
+		} else {
+			break;
+		}
+		rest = head.trim_end();
+	}
+	(rest, due_date, sessions, reference)
+}
+
+// Names that would read back as markers or annotations get a backslash in front of them.
+fn escape(name: &str) -> String {
+	let mut name = name.replace(" ^", " \\^").replace(" @", " \\@");
+	if ["[ ] ", "[x] ", REPEAT_MARKER, "\\"].iter().any(|prefix| name.starts_with(prefix)) {
+		name.insert(0, '\\');
+	}
+	name
+}
+
+fn unescape(name: &str) -> String {
+	let name = name.strip_prefix('\\').unwrap_or(name);
+	name.replace(" \\^", " ^").replace(" \\@", " @")
+}
+
+fn strip_marker(line: &str) -> &str {
+	let line = line.strip_prefix("- ")
+		.or_else(|| line.strip_prefix("* "))
+		.or_else(|| line.strip_prefix("+ "))
+		.unwrap_or_else(|| {
+			let digits = line.chars().take_while(char::is_ascii_digit).count();
+			match line[digits..].strip_prefix(". ").or_else(|| line[digits..].strip_prefix(") ")) {
+				Some(rest) if digits > 0 => rest,
+				_ => line,
+			}
+		});
+	line.strip_prefix("[ ] ")
+		.or_else(|| line.strip_prefix("[x] "))
+		.unwrap_or(line)
+		.trim()
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::NaiveDate;
+	use crate::node::Node;
+	use super::*;
+
+	fn at(day: u32, hour: u32) -> NaiveDateTime {
+		NaiveDate::from_ymd_opt(2023, 5, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+	}
+
+	fn node(id: u64, name: &str) -> Node {
+		Node { id, name: name.into(), due_date: None, session: None, sessions: Vec::new(), done: None }
+	}
+
+	fn describe(snapshot: &Snapshot) -> String {
+		match snapshot {
+			Snapshot::Node { name, due_date, sessions, children, .. } => {
+				let mut text = name.clone();
+				if let Some(due_date) = due_date {
+					_ = write!(text, " due {}", due_date);
+				}
+				for session in sessions {
+					_ = write!(text, " session {}-{}", session.start, session.end);
+				}
+				if !children.is_empty() {
+					let children: Vec<String> = children.iter().map(describe).collect();
+					_ = write!(text, " ({})", children.join(", "));
+				}
+				text
+			}
+			Snapshot::Link(_) => "link".into(),
+		}
+	}
+
+	fn names(text: &str) -> Vec<String> {
+		parse(text).iter().map(describe).collect()
+	}
+
+	#[test]
+	fn strips_bullets_numbers_and_checkboxes() {
+		let text = "- a\n* b\n+ c\n1. d\n12) e\n- [ ] f\n- [x] g\n[ ] h\n";
+		assert_eq!(names(text), vec!["a", "b", "c", "d", "e", "f", "g", "h"]);
+	}
+
+	#[test]
+	fn keeps_markers_that_are_part_of_names() {
+		assert_eq!(names("- - dash\n- 1. first\n-no space\n"), vec!["- dash", "1. first", "-no space"]);
+	}
+
+	#[test]
+	fn mixed_tabs_and_spaces_nest_by_width() {
+		let text = "- a\n\t- b\n    - c\n        - d\n  - e\n- f\n";
+		assert_eq!(names(text), vec!["a (b, c (d), e)", "f"]);
+	}
+
+	#[test]
+	fn reads_annotations_from_the_end() {
+		let text = "- pay @due(2023-05-01 09:00) @session(2023-05-02 09:00 / 2023-05-02 10:00)\n";
+		assert_eq!(names(text), vec![format!("pay due {} session {}-{}", at(1, 9), at(2, 9), at(2, 10))]);
+	}
+
+	#[test]
+	fn unknown_annotations_stay_in_the_name() {
+		assert_eq!(names("- mail bob @home\n- chapter ^two\n"), vec!["mail bob @home", "chapter ^two"]);
+	}
+
+	#[test]
+	fn repeats_become_links() {
+		let text = "- a\n\t- shared ^1\n- b\n\t- ↪ shared ^1\n- ↪ unknown ^2\n";
+		assert_eq!(names(text), vec!["a (shared)", "b (link)", "unknown"]);
+	}
+
+	#[test]
+	fn format_round_trips() {
+		let mut tasks = node(1, "Tasks");
+		tasks.due_date = Some(at(3, 17));
+		let mut chapter = node(2, "Chapter ^2");
+		chapter.sessions = vec![Session { start: at(1, 9), end: at(1, 10) }, Session { start: at(8, 9), end: at(8, 11) }];
+		let tree = Tree::from_nodes(
+			vec![
+				tasks,
+				chapter,
+				node(3, "mail @home"),
+				node(4, "[ ] literal"),
+				node(5, "↪ arrow"),
+				node(6, "\\backslash"),
+				node(7, "shared"),
+			],
+			&[(1, 2), (1, 3), (2, 4), (2, 7), (3, 5), (3, 6), (3, 7)],
+		);
+		let parsed = parse(&format(&tree, 1));
+		assert_eq!(parsed.len(), 1);
+		assert_eq!(describe(&parsed[0]), describe(&Snapshot::copy(&tree, 1)));
+	}
+}
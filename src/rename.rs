@@ -0,0 +1,122 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use regex::Regex;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum PatternField {
+	#[default]
+	Find,
+	Replace,
+	Prefix,
+	Suffix,
+}
+
+#[derive(Default)]
+pub struct RenamePattern {
+	pub find: String,
+	pub replace: String,
+	pub regex: bool,
+	pub prefix: String,
+	pub suffix: String,
+	pub focused: PatternField,
+}
+
+impl RenamePattern {
+	pub fn field_mut(&mut self, field: PatternField) -> &mut String {
+		match field {
+			PatternField::Find => &mut self.find,
+			PatternField::Replace => &mut self.replace,
+			PatternField::Prefix => &mut self.prefix,
+			PatternField::Suffix => &mut self.suffix,
+		}
+	}
+
+	pub fn rename_all<'n>(&self, names: impl IntoIterator<Item = &'n str>) -> Result<Vec<String>, regex::Error> {
+		let regex = match (self.regex, self.find.is_empty()) {
+			(true, false) => Some(Regex::new(&self.find)?),
+			_ => None,
+		};
+		Ok(names.into_iter().enumerate().map(|(i, name)| {
+			let expand_with = |template: &str, name: &str| template.replace("{n}", &(i + 1).to_string()).replace("{name}", name);
+			let expand = |template: &str| expand_with(template, name);
+			let replaced = match &regex {
+				// A literal name must not be read as capture group references by the regex replacement.
+				Some(regex) => regex.replace_all(name, expand_with(&self.replace, &name.replace('$', "$$")).as_str()).into_owned(),
+				None if !self.find.is_empty() => name.replace(&self.find, &expand(&self.replace)),
+				None if !self.replace.is_empty() => expand(&self.replace),
+				None => name.to_string(),
+			};
+			format!("{}{}{}", expand(&self.prefix), replaced, expand(&self.suffix))
+		}).collect())
+	}
+
+	pub fn clear(&mut self) {
+		*self = RenamePattern::default();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pattern(find: &str, replace: &str, regex: bool) -> RenamePattern {
+		RenamePattern {
+			find: find.into(),
+			replace: replace.into(),
+			regex,
+			..RenamePattern::default()
+		}
+	}
+
+	#[test]
+	fn plain_find_and_replace() {
+		let names = pattern("draft", "final", false).rename_all(["draft one", "two"]).unwrap();
+		assert_eq!(names, vec!["final one", "two"]);
+	}
+
+	#[test]
+	fn placeholders_in_prefix_and_suffix() {
+		let mut pattern = RenamePattern::default();
+		pattern.prefix = "{n}. ".into();
+		pattern.suffix = " ({name})".into();
+		let names = pattern.rename_all(["a", "b"]).unwrap();
+		assert_eq!(names, vec!["1. a (a)", "2. b (b)"]);
+	}
+
+	#[test]
+	fn regex_capture_groups() {
+		let names = pattern(r"(\w+) (\w+)", "$2 $1", true).rename_all(["hello world"]).unwrap();
+		assert_eq!(names, vec!["world hello"]);
+	}
+
+	#[test]
+	fn regex_keeps_dollars_in_names() {
+		let names = pattern("fee", "{name} due", true).rename_all(["Pay $5 fee"]).unwrap();
+		assert_eq!(names, vec!["Pay $5 Pay $5 fee due"]);
+	}
+
+	#[test]
+	fn empty_find_replaces_whole_name() {
+		let names = pattern("", "{n}: {name}", false).rename_all(["a", "b"]).unwrap();
+		assert_eq!(names, vec!["1: a", "2: b"]);
+	}
+
+	#[test]
+	fn invalid_regex_is_an_error() {
+		assert!(pattern("(", "", true).rename_all(["a"]).is_err());
+	}
+}
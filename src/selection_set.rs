@@ -0,0 +1,88 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::sidecar::{self, escape, invalid_data, parse_id, unescape};
+
+pub struct SelectionSet {
+	pub name: String,
+	pub selections: Vec<(u64, u64)>,
+}
+
+pub struct SelectionSets {
+	path: PathBuf,
+	sets: Vec<SelectionSet>,
+}
+
+impl SelectionSets {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "selections");
+		let mut sets = Vec::new();
+		for record in sidecar::read_records(&path)? {
+			let [name, selections] = &record[..] else { return Err(invalid_data(&path)) };
+			let mut pairs = Vec::new();
+			for pair in selections.split(',').filter(|pair| !pair.is_empty()) {
+				let (pid, id) = pair.split_once(':').ok_or_else(|| invalid_data(&path))?;
+				pairs.push((parse_id(&path, pid)?, parse_id(&path, id)?));
+			}
+			sets.push(SelectionSet { name: unescape(name), selections: pairs });
+		}
+		Ok(SelectionSets { path, sets })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		sidecar::write_records(&self.path, self.sets.iter().map(|set| [
+			escape(&set.name),
+			set.selections.iter().map(|(pid, id)| format!("{}:{}", pid, id)).collect::<Vec<_>>().join(","),
+		]))
+	}
+
+	pub fn insert(&mut self, name: String, selections: Vec<(u64, u64)>) {
+		match self.sets.iter_mut().find(|set| set.name == name) {
+			Some(set) => set.selections = selections,
+			None => self.sets.push(SelectionSet { name, selections }),
+		}
+	}
+
+	pub fn get(&self, i: usize) -> Option<&SelectionSet> {
+		self.sets.get(i)
+	}
+
+	pub fn remove(&mut self, i: usize) {
+		if i < self.sets.len() {
+			self.sets.remove(i);
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &SelectionSet> {
+		self.sets.iter()
+	}
+
+	pub fn clear(&mut self) {
+		self.sets.clear();
+	}
+
+	pub fn rekey(&mut self, ids: &HashMap<u64, u64>) {
+		for set in &mut self.sets {
+			for (pid, id) in &mut set.selections {
+				*pid = ids.get(pid).copied().unwrap_or(*pid);
+				*id = ids.get(id).copied().unwrap_or(*id);
+			}
+		}
+	}
+}
@@ -0,0 +1,244 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{Duration, NaiveDateTime};
+use grus_lib::types::Session;
+use crate::sidecar::{self, format_datetime, invalid_data, parse_datetime, parse_id};
+
+const HORIZON_WEEKS: i64 = 8;
+
+pub struct Series {
+	pub id: u64,
+	pub nodes: Vec<u64>,
+	pub first: Session,
+	pub until: NaiveDateTime,
+	expanded: Option<NaiveDateTime>,
+}
+
+impl Series {
+	pub fn occurrences(&self) -> impl Iterator<Item = Session> + '_ {
+		(0..).map(|week| Session {
+			start: self.first.start + Duration::weeks(week),
+			end: self.first.end + Duration::weeks(week),
+		}).take_while(|session| session.start <= self.until)
+	}
+
+	// Occurrences that already started are tracked history and are left alone.
+	pub fn upcoming(&self, now: NaiveDateTime) -> impl Iterator<Item = (u64, Session)> + '_ {
+		let expanded = self.expanded;
+		self.occurrences()
+			.take_while(move |session| expanded.is_some_and(|expanded| session.start <= expanded))
+			.filter(move |session| session.start > now)
+			.flat_map(|session| self.nodes.iter().map(move |&id| (id, session)))
+	}
+
+	pub fn set(&mut self, first: Session, until: NaiveDateTime, now: NaiveDateTime) {
+		self.first = first;
+		self.until = until;
+		self.expanded = Some(now);
+	}
+
+	fn pending(&self, horizon: NaiveDateTime) -> Vec<(u64, Session)> {
+		self.occurrences()
+			.skip_while(|session| self.expanded.is_some_and(|expanded| session.start <= expanded))
+			.take_while(|session| session.start <= horizon)
+			.flat_map(|session| self.nodes.iter().map(move |&id| (id, session)))
+			.collect()
+	}
+}
+
+impl Display for Series {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} {} to {} until {}",
+			self.first.start.format("%A"),
+			self.first.start.format("%-I:%M %p"),
+			self.first.end.format("%-I:%M %p"),
+			self.until.format("%e %b %Y"),
+		)
+	}
+}
+
+pub struct SeriesList {
+	path: PathBuf,
+	series: Vec<Series>,
+	next_id: u64,
+}
+
+impl SeriesList {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "series");
+		let mut series = Vec::new();
+		for record in sidecar::read_records(&path)? {
+			let [id, nodes, start, end, until, expanded] = &record[..] else {
+				return Err(invalid_data(&path));
+			};
+			series.push(Series {
+				id: parse_id(&path, id)?,
+				nodes: nodes.split(',')
+					.filter(|id| !id.is_empty())
+					.map(|id| parse_id(&path, id))
+					.collect::<io::Result<_>>()?,
+				first: Session { start: parse_datetime(&path, start)?, end: parse_datetime(&path, end)? },
+				until: parse_datetime(&path, until)?,
+				expanded: if expanded.is_empty() { None } else { Some(parse_datetime(&path, expanded)?) },
+			});
+		}
+		let next_id = series.iter().map(|series| series.id + 1).max().unwrap_or(0);
+		Ok(SeriesList { path, series, next_id })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		sidecar::write_records(&self.path, self.series.iter().map(|series| [
+			series.id.to_string(),
+			series.nodes.iter().map(u64::to_string).collect::<Vec<_>>().join(","),
+			format_datetime(series.first.start),
+			format_datetime(series.first.end),
+			format_datetime(series.until),
+			series.expanded.map(format_datetime).unwrap_or_default(),
+		]))
+	}
+
+	pub fn insert(&mut self, nodes: Vec<u64>, first: Session, until: NaiveDateTime) -> u64 {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.series.push(Series { id, nodes, first, until, expanded: None });
+		id
+	}
+
+	pub fn get(&self, id: u64) -> Option<&Series> {
+		self.series.iter().find(|series| series.id == id)
+	}
+
+	pub fn get_mut(&mut self, id: u64) -> Option<&mut Series> {
+		self.series.iter_mut().find(|series| series.id == id)
+	}
+
+	pub fn remove(&mut self, id: u64) -> Option<Series> {
+		let i = self.series.iter().position(|series| series.id == id)?;
+		Some(self.series.remove(i))
+	}
+
+	pub fn retain_nodes(&mut self, mut f: impl FnMut(u64) -> bool) {
+		for series in &mut self.series {
+			series.nodes.retain(|&id| f(id));
+		}
+		self.series.retain(|series| !series.nodes.is_empty());
+	}
+
+	pub fn clear(&mut self) {
+		self.series.clear();
+	}
+
+	pub fn rekey(&mut self, ids: &HashMap<u64, u64>) {
+		for series in &mut self.series {
+			for id in &mut series.nodes {
+				*id = ids.get(id).copied().unwrap_or(*id);
+			}
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Series> {
+		self.series.iter()
+	}
+
+	pub fn expand(&mut self, now: NaiveDateTime) -> Vec<(u64, Session)> {
+		let horizon = now + Duration::weeks(HORIZON_WEEKS);
+		let mut sessions = Vec::new();
+		for series in &mut self.series {
+			let pending = series.pending(horizon);
+			if let Some((_, last)) = pending.last() {
+				series.expanded = Some(last.start);
+			}
+			sessions.extend(pending);
+		}
+		sessions
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::fs;
+	use std::process;
+	use chrono::NaiveDate;
+	use super::*;
+
+	fn at(day: u32, hour: u32) -> NaiveDateTime {
+		NaiveDate::from_ymd_opt(2023, 5, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+	}
+
+	fn series(until: NaiveDateTime) -> Series {
+		Series {
+			id: 0,
+			nodes: vec![3, 7],
+			first: Session { start: at(1, 9), end: at(1, 10) },
+			until,
+			expanded: None,
+		}
+	}
+
+	#[test]
+	fn occurrences_repeat_weekly_until_end() {
+		let starts: Vec<_> = series(at(15, 9)).occurrences().map(|session| session.start).collect();
+		assert_eq!(starts, vec![at(1, 9), at(8, 9), at(15, 9)]);
+	}
+
+	#[test]
+	fn pending_skips_expanded_and_stops_at_horizon() {
+		let mut series = series(at(29, 9));
+		series.expanded = Some(at(1, 9));
+		let pending: Vec<_> = series.pending(at(15, 12)).into_iter()
+			.map(|(id, session)| (id, session.start, session.end))
+			.collect();
+		assert_eq!(pending, vec![
+			(3, at(8, 9), at(8, 10)),
+			(7, at(8, 9), at(8, 10)),
+			(3, at(15, 9), at(15, 10)),
+			(7, at(15, 9), at(15, 10)),
+		]);
+	}
+
+	#[test]
+	fn upcoming_keeps_past_occurrences() {
+		let mut series = series(at(29, 9));
+		series.expanded = Some(at(15, 9));
+		let starts: Vec<_> = series.upcoming(at(8, 12)).map(|(_, session)| session.start).collect();
+		assert_eq!(starts, vec![at(15, 9), at(15, 9)]);
+	}
+
+	#[test]
+	fn sidecar_round_trip() {
+		let store_path = env::temp_dir().join(format!("grus-series-test-{}", process::id()));
+		let mut list = SeriesList::open(&store_path).unwrap();
+		list.insert(vec![3, 7], Session { start: at(1, 9), end: at(1, 10) }, at(29, 9));
+		list.insert(Vec::new(), Session { start: at(2, 9), end: at(2, 10) }, at(30, 9));
+		list.expand(at(8, 12));
+		list.save().unwrap();
+
+		let reopened = SeriesList::open(&store_path).unwrap();
+		fs::remove_file(sidecar::sidecar_path(&store_path, "series")).unwrap();
+		let fields = |list: &SeriesList| list.iter()
+			.map(|series| (series.id, series.nodes.clone(), series.first.start, series.first.end, series.until, series.expanded))
+			.collect::<Vec<_>>();
+		assert_eq!(fields(&list), fields(&reopened));
+	}
+}
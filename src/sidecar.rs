@@ -0,0 +1,108 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use chrono::NaiveDateTime;
+
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+pub fn sidecar_path(store_path: &Path, ext: &str) -> PathBuf {
+	let mut path = OsString::from(store_path.as_os_str());
+	path.push(".");
+	path.push(ext);
+	path.into()
+}
+
+pub fn read_records(path: &Path) -> io::Result<Vec<Vec<String>>> {
+	let file = match File::open(path) {
+		Ok(file) => file,
+		Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(err) => return Err(err),
+	};
+	let mut records = Vec::new();
+	for line in BufReader::new(file).lines() {
+		let line = line?;
+		if line.is_empty() { continue }
+		records.push(line.split('\t').map(String::from).collect());
+	}
+	Ok(records)
+}
+
+pub fn write_records<I, R>(path: &Path, records: I) -> io::Result<()>
+where
+	I: IntoIterator<Item = R>,
+	R: IntoIterator<Item = String>,
+{
+	// Write to a temporary file first so that a crash never leaves a half written file behind.
+	let tmp_path = sidecar_path(path, "tmp");
+	let mut writer = BufWriter::new(File::create(&tmp_path)?);
+	for record in records {
+		let fields: Vec<String> = record.into_iter().collect();
+		writeln!(writer, "{}", fields.join("\t"))?;
+	}
+	writer.into_inner()?.sync_all()?;
+	fs::rename(tmp_path, path)
+}
+
+pub fn invalid_data(path: &Path) -> io::Error {
+	io::Error::new(ErrorKind::InvalidData, format!("Malformed record in {}", path.display()))
+}
+
+pub fn format_datetime(dt: NaiveDateTime) -> String {
+	dt.format(DATETIME_FORMAT).to_string()
+}
+
+pub fn parse_datetime(path: &Path, s: &str) -> io::Result<NaiveDateTime> {
+	NaiveDateTime::parse_from_str(s, DATETIME_FORMAT).map_err(|_| invalid_data(path))
+}
+
+pub fn parse_id(path: &Path, s: &str) -> io::Result<u64> {
+	s.parse().map_err(|_| invalid_data(path))
+}
+
+pub fn escape(s: &str) -> String {
+	let mut escaped = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'\\' => escaped.push_str("\\\\"),
+			'\t' => escaped.push_str("\\t"),
+			'\n' => escaped.push_str("\\n"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+pub fn unescape(s: &str) -> String {
+	let mut unescaped = String::with_capacity(s.len());
+	let mut chars = s.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			unescaped.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('t') => unescaped.push('\t'),
+			Some('n') => unescaped.push('\n'),
+			Some(c) => unescaped.push(c),
+			None => {}
+		}
+	}
+	unescaped
+}
@@ -0,0 +1,222 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::iter::Peekable;
+use std::path::Path;
+use chrono::{Duration, NaiveDateTime};
+use grus_lib::{Error, StoreWriter};
+use grus_lib::types::Session;
+use crate::node::{Node, Tree};
+use crate::sidecar::{escape, format_datetime, invalid_data, parse_datetime, parse_id, unescape};
+
+pub fn resolve(remap: &HashMap<u64, u64>, mut id: u64) -> u64 {
+	while let Some(&new_id) = remap.get(&id) { id = new_id }
+	id
+}
+
+#[derive(Clone)]
+pub enum Snapshot {
+	Node {
+		id: u64,
+		name: String,
+		due_date: Option<NaiveDateTime>,
+		sessions: Vec<Session>,
+		children: Vec<Snapshot>,
+	},
+	Link(u64),
+}
+
+impl Snapshot {
+	pub fn capture(tree: &Tree, id: u64, removed: &HashSet<u64>) -> Self {
+		Self::capture_inner(tree, id, removed, &mut HashSet::new())
+	}
+
+	fn capture_inner(tree: &Tree, id: u64, removed: &HashSet<u64>, seen: &mut HashSet<u64>) -> Self {
+		if !removed.contains(&id) || !seen.insert(id) { return Snapshot::Link(id) }
+		let node = tree.node_at(id);
+		Snapshot::Node {
+			id,
+			name: node.name.clone(),
+			due_date: node.due_date,
+			sessions: node.sessions.clone(),
+			children: tree.children(id).map(|child| Self::capture_inner(tree, child.id, removed, seen)).collect(),
+		}
+	}
+
+	pub fn copy(tree: &Tree, id: u64) -> Self {
+		Self::capture(tree, id, &tree.descendants(id))
+	}
+
+	pub fn shift_dates(&mut self, offset: Duration) {
+		let Snapshot::Node { due_date, sessions, children, .. } = self else { return };
+		if let Some(due_date) = due_date {
+			*due_date += offset;
+		}
+		for session in sessions {
+			session.start += offset;
+			session.end += offset;
+		}
+		for child in children {
+			child.shift_dates(offset);
+		}
+	}
+
+	pub fn clear_sessions(&mut self) {
+		let Snapshot::Node { sessions, children, .. } = self else { return };
+		sessions.clear();
+		for child in children {
+			child.clear_sessions();
+		}
+	}
+
+	// History remaps chain through every undo and redo, while a copy maps only its own ids,
+	// which may collide with unrelated store ids.
+	pub fn restore(
+		&self,
+		writer: &mut StoreWriter,
+		tree: &mut Tree,
+		pid: u64,
+		ids: &mut HashMap<u64, u64>,
+		chained: bool,
+	) -> Result<Option<u64>, Error> {
+		match self {
+			Snapshot::Node { id, name, due_date, sessions, children } => {
+				let new_id = writer.add_child(pid, name)?;
+				ids.insert(*id, new_id);
+				if let Some(due_date) = due_date {
+					writer.set_due_date(new_id, *due_date)?;
+				}
+				for session in sessions {
+					writer.add_session(new_id, session)?;
+				}
+				tree.add_node(pid, Node {
+					id: new_id,
+					name: name.clone(),
+					due_date: *due_date,
+					sessions: sessions.clone(),
+					..Node::default()
+				});
+				for child in children {
+					child.restore(writer, tree, new_id, ids, chained)?;
+				}
+				Ok(Some(new_id))
+			}
+			Snapshot::Link(id) => {
+				let new_id = if chained { resolve(ids, *id) } else { ids.get(id).copied().unwrap_or(*id) };
+				if new_id != *id || tree.contains(new_id) {
+					writer.share(new_id, pid)?;
+					tree.link(pid, new_id);
+					Ok(Some(new_id))
+				} else {
+					Ok(None)
+				}
+			}
+		}
+	}
+
+	pub fn node_ids(&self, ids: &mut HashSet<u64>) {
+		let Snapshot::Node { id, children, .. } = self else { return };
+		ids.insert(*id);
+		for child in children {
+			child.node_ids(ids);
+		}
+	}
+
+	pub fn node_name(&self, id: u64) -> Option<&str> {
+		let Snapshot::Node { id: node_id, name, children, .. } = self else { return None };
+		if *node_id == id { return Some(name) }
+		children.iter().find_map(|child| child.node_name(id))
+	}
+
+	pub fn id(&self) -> u64 {
+		match *self {
+			Snapshot::Node { id, .. } | Snapshot::Link(id) => id,
+		}
+	}
+
+	pub fn name<'s>(&'s self, tree: &'s Tree) -> &'s str {
+		match self {
+			Snapshot::Node { name, .. } => name,
+			Snapshot::Link(id) if tree.contains(*id) => &tree.node_at(*id).name,
+			Snapshot::Link(_) => "",
+		}
+	}
+
+	pub fn node_count(&self) -> usize {
+		match self {
+			Snapshot::Node { children, .. } => 1 + children.iter().map(Snapshot::node_count).sum::<usize>(),
+			Snapshot::Link(_) => 0,
+		}
+	}
+
+	pub fn to_records(&self, depth: usize, records: &mut Vec<Vec<String>>) {
+		match self {
+			Snapshot::Node { id, name, due_date, sessions, children } => {
+				records.push(vec![
+					"N".into(),
+					depth.to_string(),
+					id.to_string(),
+					escape(name),
+					due_date.map(format_datetime).unwrap_or_default(),
+				]);
+				for session in sessions {
+					records.push(vec!["S".into(), format_datetime(session.start), format_datetime(session.end)]);
+				}
+				for child in children {
+					child.to_records(depth + 1, records);
+				}
+			}
+			Snapshot::Link(id) => records.push(vec!["L".into(), depth.to_string(), id.to_string()]),
+		}
+	}
+
+	pub fn from_records<I>(path: &Path, records: &mut Peekable<I>, depth: usize) -> io::Result<Self>
+	where
+		I: Iterator<Item = Vec<String>>,
+	{
+		let record = records.next().ok_or_else(|| invalid_data(path))?;
+		match &record[..] {
+			[kind, d, id] if kind == "L" && parse_id(path, d)? == depth as u64 => {
+				Ok(Snapshot::Link(parse_id(path, id)?))
+			}
+			[kind, d, id, name, due_date] if kind == "N" && parse_id(path, d)? == depth as u64 => {
+				let mut sessions = Vec::new();
+				while let Some([_, start, end]) = records.next_if(|r| r.len() == 3 && r[0] == "S").as_deref() {
+					sessions.push(Session { start: parse_datetime(path, start)?, end: parse_datetime(path, end)? });
+				}
+				let mut children = Vec::new();
+				while records.peek().is_some_and(|r| Self::is_at_depth(r, depth + 1)) {
+					children.push(Self::from_records(path, records, depth + 1)?);
+				}
+				Ok(Snapshot::Node {
+					id: parse_id(path, id)?,
+					name: unescape(name),
+					due_date: if due_date.is_empty() { None } else { Some(parse_datetime(path, due_date)?) },
+					sessions,
+					children,
+				})
+			}
+			_ => Err(invalid_data(path)),
+		}
+	}
+
+	fn is_at_depth(record: &[String], depth: usize) -> bool {
+		record.first().is_some_and(|kind| kind == "N" || kind == "L")
+			&& record.get(1).is_some_and(|d| *d == depth.to_string())
+	}
+}
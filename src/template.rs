@@ -0,0 +1,138 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDateTime;
+use crate::sidecar::{self, escape, format_datetime, invalid_data, parse_datetime, unescape};
+use crate::snapshot::Snapshot;
+
+pub struct Template {
+	pub name: String,
+	pub anchor: NaiveDateTime,
+	pub snapshot: Snapshot,
+}
+
+impl Template {
+	pub fn new(name: String, snapshot: Snapshot, now: NaiveDateTime) -> Self {
+		let anchor = match &snapshot {
+			Snapshot::Node { due_date: Some(due_date), .. } => *due_date,
+			_ => earliest_date(&snapshot).unwrap_or(now),
+		};
+		Template { name, anchor, snapshot }
+	}
+
+	pub fn placeholders(&self) -> Vec<String> {
+		let mut placeholders = Vec::new();
+		collect_placeholders(&self.snapshot, &mut placeholders);
+		placeholders
+	}
+
+	pub fn instantiate(&self, values: &HashMap<String, String>, anchor: NaiveDateTime) -> Snapshot {
+		let mut snapshot = self.snapshot.clone();
+		substitute(&mut snapshot, values);
+		snapshot.shift_dates(anchor - self.anchor);
+		snapshot
+	}
+}
+
+pub struct Templates {
+	path: PathBuf,
+	templates: Vec<Template>,
+}
+
+impl Templates {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "templates");
+		let mut templates = Vec::new();
+		let mut records = sidecar::read_records(&path)?.into_iter().peekable();
+		while let Some(record) = records.next() {
+			let [kind, name, anchor] = &record[..] else { return Err(invalid_data(&path)) };
+			if kind != "T" { return Err(invalid_data(&path)) }
+			templates.push(Template {
+				name: unescape(name),
+				anchor: parse_datetime(&path, anchor)?,
+				snapshot: Snapshot::from_records(&path, &mut records, 0)?,
+			});
+		}
+		Ok(Templates { path, templates })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		let mut records = Vec::new();
+		for template in &self.templates {
+			records.push(vec!["T".into(), escape(&template.name), format_datetime(template.anchor)]);
+			template.snapshot.to_records(0, &mut records);
+		}
+		sidecar::write_records(&self.path, records)
+	}
+
+	pub fn insert(&mut self, template: Template) {
+		match self.templates.iter_mut().find(|t| t.name == template.name) {
+			Some(t) => *t = template,
+			None => self.templates.push(template),
+		}
+	}
+
+	pub fn get(&self, i: usize) -> Option<&Template> {
+		self.templates.get(i)
+	}
+
+	pub fn remove(&mut self, i: usize) {
+		if i < self.templates.len() {
+			self.templates.remove(i);
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Template> {
+		self.templates.iter()
+	}
+}
+
+fn earliest_date(snapshot: &Snapshot) -> Option<NaiveDateTime> {
+	let Snapshot::Node { due_date, sessions, children, .. } = snapshot else { return None };
+	due_date.iter()
+		.copied()
+		.chain(sessions.iter().map(|session| session.start))
+		.chain(children.iter().filter_map(earliest_date))
+		.min()
+}
+
+fn collect_placeholders(snapshot: &Snapshot, placeholders: &mut Vec<String>) {
+	let Snapshot::Node { name, children, .. } = snapshot else { return };
+	let mut rest = name.as_str();
+	while let Some((_, tail)) = rest.split_once('{') {
+		let Some((placeholder, tail)) = tail.split_once('}') else { break };
+		if !placeholder.is_empty() && !placeholders.iter().any(|p| p == placeholder) {
+			placeholders.push(placeholder.to_string());
+		}
+		rest = tail;
+	}
+	for child in children {
+		collect_placeholders(child, placeholders);
+	}
+}
+
+fn substitute(snapshot: &mut Snapshot, values: &HashMap<String, String>) {
+	let Snapshot::Node { name, children, .. } = snapshot else { return };
+	for (placeholder, value) in values {
+		*name = name.replace(&format!("{{{}}}", placeholder), value);
+	}
+	for child in children {
+		substitute(child, values);
+	}
+}
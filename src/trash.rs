@@ -0,0 +1,94 @@
+// This file is part of grus-gui, a hierarchical task management application.
+// Copyright (C) 2023 Rishabh Das
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{Duration, NaiveDateTime};
+use crate::sidecar::{self, format_datetime, invalid_data, parse_datetime, parse_id};
+use crate::snapshot::Snapshot;
+
+const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+pub struct TrashEntry {
+	pub pid: u64,
+	pub deleted_at: NaiveDateTime,
+	pub snapshot: Snapshot,
+}
+
+pub struct Trash {
+	path: PathBuf,
+	pub retention_days: u32,
+	entries: Vec<TrashEntry>,
+}
+
+impl Trash {
+	pub fn open(store_path: &Path) -> io::Result<Self> {
+		let path = sidecar::sidecar_path(store_path, "trash");
+		let mut retention_days = DEFAULT_RETENTION_DAYS;
+		let mut entries = Vec::new();
+		let mut records = sidecar::read_records(&path)?.into_iter().peekable();
+		while let Some(record) = records.next() {
+			match &record[..] {
+				[kind, days] if kind == "R" => {
+					retention_days = days.parse().map_err(|_| invalid_data(&path))?;
+				}
+				[kind, pid, deleted_at] if kind == "E" => entries.push(TrashEntry {
+					pid: parse_id(&path, pid)?,
+					deleted_at: parse_datetime(&path, deleted_at)?,
+					snapshot: Snapshot::from_records(&path, &mut records, 0)?,
+				}),
+				_ => return Err(invalid_data(&path)),
+			}
+		}
+		Ok(Trash { path, retention_days, entries })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		let mut records = vec![vec!["R".into(), self.retention_days.to_string()]];
+		for entry in &self.entries {
+			records.push(vec!["E".into(), entry.pid.to_string(), format_datetime(entry.deleted_at)]);
+			entry.snapshot.to_records(0, &mut records);
+		}
+		sidecar::write_records(&self.path, records)
+	}
+
+	pub fn push(&mut self, pid: u64, snapshot: Snapshot, deleted_at: NaiveDateTime) {
+		self.entries.push(TrashEntry { pid, deleted_at, snapshot });
+	}
+
+	pub fn take(&mut self, i: usize) -> Option<TrashEntry> {
+		if i < self.entries.len() { Some(self.entries.remove(i)) } else { None }
+	}
+
+	pub fn discard(&mut self, pid: u64, id: u64) {
+		if let Some(i) = self.entries.iter().rposition(|entry| entry.pid == pid && entry.snapshot.id() == id) {
+			self.entries.remove(i);
+		}
+	}
+
+	pub fn purge(&mut self, now: NaiveDateTime) {
+		let retention = Duration::days(self.retention_days as i64);
+		self.entries.retain(|entry| entry.deleted_at + retention > now);
+	}
+
+	pub fn clear(&mut self) {
+		self.entries.clear();
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &TrashEntry> {
+		self.entries.iter()
+	}
+}